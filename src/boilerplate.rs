@@ -0,0 +1,58 @@
+use regex::Regex;
+
+/// Patterns that mark front and back matter to leave out of the chunks. Text before a
+/// start marker and from an end marker onwards is dropped, as are spine items whose TOC
+/// title matches one of `skip_titles`. The defaults cover Project Gutenberg books.
+#[derive(Debug, Clone)]
+pub struct BoilerplateFilter {
+    pub start_markers: Vec<Regex>,
+    pub end_markers: Vec<Regex>,
+    pub skip_titles: Vec<Regex>,
+}
+
+impl Default for BoilerplateFilter {
+    fn default() -> Self {
+        let patterns = |patterns: &[&str]| patterns.iter().map(|p| Regex::new(p).expect("valid pattern")).collect();
+        Self {
+            start_markers: patterns(&[r"(?i)START OF (THE|THIS) PROJECT GUTENBERG"]),
+            end_markers: patterns(&[r"(?i)END OF (THE|THIS) PROJECT GUTENBERG"]),
+            skip_titles: patterns(&[r"(?i)project gutenberg license", r"(?i)^copyright\b"]),
+        }
+    }
+}
+
+impl BoilerplateFilter {
+    pub fn skips_title(&self, title: &str) -> bool {
+        self.skip_titles.iter().any(|pattern| pattern.is_match(title))
+    }
+}
+
+/// Tracks where the book proper starts and ends while spine items are visited in order.
+#[derive(Debug, Default)]
+pub(crate) struct BoilerplateState {
+    started: bool,
+    ended: bool,
+}
+
+impl BoilerplateState {
+    /// Returns the part of `markdown` that belongs to the book, cutting whole lines.
+    pub(crate) fn trim<'a>(&mut self, filter: &BoilerplateFilter, markdown: &'a str) -> &'a str {
+        if self.ended {
+            return "";
+        }
+        let mut text = markdown;
+        if !self.started {
+            if let Some(found) = filter.start_markers.iter().find_map(|pattern| pattern.find(text)) {
+                self.started = true;
+                let line_end = text[found.end()..].find('\n').map_or(text.len(), |idx| found.end() + idx);
+                text = &text[line_end..];
+            }
+        }
+        if let Some(found) = filter.end_markers.iter().find_map(|pattern| pattern.find(text)) {
+            self.ended = true;
+            let line_start = text[..found.start()].rfind('\n').map_or(0, |idx| idx + 1);
+            text = &text[..line_start];
+        }
+        text
+    }
+}
@@ -0,0 +1,61 @@
+use crate::hash::stable_hash;
+use crate::{CipherError, Result};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Content-addressed store of embeddings on disk, one JSON file per (embedder, text) pair.
+/// Entries are keyed by [`Embedder::cache_key`](crate::Embedder::cache_key).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddingCache {
+    dir: PathBuf,
+}
+
+impl EmbeddingCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the cached embedding, or `None` on a miss or an unreadable entry.
+    pub fn get(&self, key: &str, text: &str) -> Option<Vec<f64>> {
+        let entry: Value = serde_json::from_str(&fs::read_to_string(self.entry_path(key, text)).ok()?).ok()?;
+        // Guard against hash collisions by checking the stored key.
+        if entry["key"] != key || entry["text"] != text {
+            return None;
+        }
+        entry["embedding"]
+            .as_array()?
+            .iter()
+            .map(Value::as_f64)
+            .collect()
+    }
+
+    pub fn put(&self, key: &str, text: &str, embedding: &[f64]) -> Result<()> {
+        fs::create_dir_all(&self.dir).map_err(|e| CipherError::file(&self.dir, e))?;
+        let path = self.entry_path(key, text);
+        let entry = json!({ "key": key, "text": text, "embedding": embedding });
+        // Write to a temporary file first so concurrent readers never see a partial entry.
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&tmp, entry.to_string()).map_err(|e| CipherError::file(&tmp, e))?;
+        fs::rename(&tmp, &path).map_err(|e| CipherError::file(&path, e))?;
+        Ok(())
+    }
+
+    fn entry_path(&self, key: &str, text: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", stable_hash(&[key, text])))
+    }
+}
+
+/// The platform cache directory for embeddings: `$XDG_CACHE_HOME/cipher/embeddings`,
+/// falling back to `~/.cache/cipher/embeddings`.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(base.join("cipher").join("embeddings"))
+}
@@ -0,0 +1,273 @@
+/// Size limits for [`chunk_markdown`], both measured in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkOptions {
+    pub max_chars: usize,
+    pub overlap: usize,
+    /// Chunks shorter than this are dropped, or merged when `merge_short` is set.
+    pub min_chars: usize,
+    /// Join chunks shorter than `min_chars` onto a neighbour instead of dropping them, so
+    /// no text is lost. A merged chunk may exceed `max_chars` by roughly `min_chars`.
+    pub merge_short: bool,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            max_chars: 1000,
+            overlap: 100,
+            min_chars: 0,
+            merge_short: false,
+        }
+    }
+}
+
+const PARAGRAPH_SEPARATOR: &str = "\n\n";
+
+/// A chunk and the headings it falls under, outermost first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub text: String,
+    pub headings: Vec<String>,
+}
+
+/// Splits markdown into chunks of at most `max_chars` characters. Paragraphs are kept
+/// whole when they fit; longer ones are split between words. Consecutive chunks share
+/// up to `overlap` characters, cut on a word boundary.
+pub fn chunk_markdown(markdown: &str, options: &ChunkOptions) -> Vec<String> {
+    chunk_markdown_with_headings(markdown, options)
+        .into_iter()
+        .map(|chunk| chunk.text)
+        .collect()
+}
+
+/// Like [`chunk_markdown`], but also records the headings in effect where each chunk
+/// starts. A chunk that opens with a heading falls under that heading.
+pub fn chunk_markdown_with_headings(markdown: &str, options: &ChunkOptions) -> Vec<Chunk> {
+    let max_chars = options.max_chars.max(1);
+    let overlap = options.overlap.min(max_chars / 2);
+    // Leave room for the overlap and the separator that joins it to new content.
+    let budget = if overlap == 0 {
+        max_chars
+    } else {
+        max_chars.saturating_sub(overlap + PARAGRAPH_SEPARATOR.len()).max(1)
+    };
+
+    let mut headings = HeadingTracker::default();
+    let mut pieces = Vec::new();
+    for paragraph in paragraphs(markdown) {
+        headings.visit(paragraph);
+        if char_len(paragraph) <= budget {
+            pieces.push((paragraph.to_string(), headings.path()));
+        } else {
+            let path = headings.path();
+            pieces.extend(split_words(paragraph, budget).into_iter().map(|piece| (piece, path.clone())));
+        }
+    }
+
+    // Each chunk with the byte offset where its text stops repeating the previous chunk.
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_headings = Vec::new();
+    // Whether `current` holds anything beyond the overlap carried over from the last chunk.
+    let mut has_new_piece = false;
+    let mut new_start = 0;
+    for (piece, piece_headings) in pieces {
+        if !current.is_empty() && char_len(&current) + PARAGRAPH_SEPARATOR.len() + char_len(&piece) > max_chars {
+            // The overlap tail always has room for the next piece, see `budget`.
+            let tail = overlap_tail(&current, overlap);
+            let chunk = Chunk {
+                text: std::mem::replace(&mut current, tail),
+                headings: std::mem::take(&mut current_headings),
+            };
+            chunks.push((chunk, new_start));
+            new_start = if current.is_empty() { 0 } else { current.len() + PARAGRAPH_SEPARATOR.len() };
+            has_new_piece = false;
+        }
+        if !current.is_empty() {
+            current.push_str(PARAGRAPH_SEPARATOR);
+        }
+        current.push_str(&piece);
+        if !has_new_piece {
+            current_headings = piece_headings;
+            has_new_piece = true;
+        }
+    }
+    if !current.is_empty() {
+        let chunk = Chunk {
+            text: current,
+            headings: current_headings,
+        };
+        chunks.push((chunk, new_start));
+    }
+    if options.merge_short {
+        return merge_short_chunks(chunks, options.min_chars);
+    }
+    chunks
+        .into_iter()
+        .map(|(chunk, _)| chunk)
+        .filter(|chunk| char_len(&chunk.text) >= options.min_chars)
+        .collect()
+}
+
+/// Appends each short chunk's new text to the chunk before it. Short chunks at the start
+/// are carried forward into the next one instead.
+fn merge_short_chunks(chunks: Vec<(Chunk, usize)>, min_chars: usize) -> Vec<Chunk> {
+    let mut merged: Vec<Chunk> = Vec::new();
+    let mut pending: Option<Chunk> = None;
+    for (mut chunk, mut new_start) in chunks {
+        if let Some(short) = pending.take() {
+            chunk.text = format!("{}{}{}", short.text, PARAGRAPH_SEPARATOR, &chunk.text[new_start..]);
+            chunk.headings = short.headings;
+            new_start = 0;
+        }
+        if char_len(&chunk.text) >= min_chars {
+            merged.push(chunk);
+        } else if let Some(last) = merged.last_mut() {
+            last.text.push_str(PARAGRAPH_SEPARATOR);
+            last.text.push_str(&chunk.text[new_start..]);
+        } else {
+            pending = Some(chunk);
+        }
+    }
+    // Text too short to ever reach `min_chars` is still kept.
+    merged.extend(pending);
+    merged
+}
+
+/// The headings in effect at the start of `markdown`, i.e. after its first paragraph.
+pub(crate) fn leading_headings(markdown: &str) -> Vec<String> {
+    let mut headings = HeadingTracker::default();
+    if let Some(paragraph) = paragraphs(markdown).next() {
+        headings.visit(paragraph);
+    }
+    headings.path()
+}
+
+fn paragraphs(markdown: &str) -> impl Iterator<Item = &str> {
+    markdown.split(PARAGRAPH_SEPARATOR).map(str::trim).filter(|p| !p.is_empty())
+}
+
+#[derive(Debug, Default)]
+struct HeadingTracker {
+    stack: Vec<(usize, String)>,
+}
+
+impl HeadingTracker {
+    fn visit(&mut self, paragraph: &str) {
+        if let Some((level, title)) = parse_heading(paragraph) {
+            while self.stack.last().is_some_and(|(top, _)| *top >= level) {
+                self.stack.pop();
+            }
+            self.stack.push((level, title));
+        }
+    }
+
+    fn path(&self) -> Vec<String> {
+        self.stack.iter().map(|(_, title)| title.clone()).collect()
+    }
+}
+
+/// Recognises ATX (`## Title ##`) and setext (`Title` underlined with `=` or `-`)
+/// headings, which are the two forms html2md emits.
+fn parse_heading(paragraph: &str) -> Option<(usize, String)> {
+    let hashes = paragraph.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&hashes) && paragraph[hashes..].starts_with(' ') {
+        // A closing `#` run means the heading spans the whole paragraph, line breaks included.
+        let text = if paragraph.ends_with('#') {
+            paragraph
+        } else {
+            paragraph.lines().next().unwrap_or_default()
+        };
+        return clean_heading(text[hashes..].trim_end_matches('#')).map(|title| (hashes, title));
+    }
+
+    let (text, underline) = paragraph.rsplit_once('\n')?;
+    let level = if underline.len() >= 3 && underline.chars().all(|c| c == '=') {
+        1
+    } else if underline.len() >= 3 && underline.chars().all(|c| c == '-') {
+        2
+    } else {
+        return None;
+    };
+    clean_heading(text).map(|title| (level, title))
+}
+
+fn clean_heading(text: &str) -> Option<String> {
+    // html2md wraps anchored headings in empty links, e.g. `[]()[TRAPS.]()`.
+    let mut title = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        let link = rest[open..].find("](").and_then(|close| {
+            let close = open + close;
+            rest[close..].find(')').map(|end| (close, close + end))
+        });
+        let Some((close, end)) = link else {
+            break;
+        };
+        title.push_str(&rest[..open]);
+        title.push_str(&rest[open + 1..close]);
+        rest = &rest[end + 1..];
+    }
+    title.push_str(rest);
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
+fn char_len(text: &str) -> usize {
+    text.chars().count()
+}
+
+fn split_words(text: &str, budget: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        for word in split_long_word(word, budget) {
+            let separator = if current.is_empty() { 0 } else { 1 };
+            if !current.is_empty() && char_len(&current) + separator + char_len(word) > budget {
+                pieces.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+    pieces
+}
+
+/// A single word longer than the budget has no word boundary to respect, so it is cut
+/// at character boundaries.
+fn split_long_word(word: &str, budget: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = word;
+    while char_len(rest) > budget {
+        let (idx, _) = rest.char_indices().nth(budget).unwrap_or((rest.len(), ' '));
+        parts.push(&rest[..idx]);
+        rest = &rest[idx..];
+    }
+    parts.push(rest);
+    parts
+}
+
+fn overlap_tail(chunk: &str, overlap: usize) -> String {
+    if overlap == 0 {
+        return String::new();
+    }
+    let len = char_len(chunk);
+    if len <= overlap {
+        return chunk.to_string();
+    }
+    let (start, _) = chunk.char_indices().nth(len - overlap).unwrap_or((0, ' '));
+    let tail = &chunk[start..];
+    // Only start the overlap on a word boundary, dropping a leading partial word.
+    let starts_mid_word = !chunk[..start].ends_with(char::is_whitespace);
+    let tail = if starts_mid_word {
+        tail.find(char::is_whitespace).map_or("", |idx| &tail[idx..])
+    } else {
+        tail
+    };
+    tail.trim().to_string()
+}
@@ -0,0 +1,54 @@
+use crate::similarity::cosine_similarity;
+use crate::Result;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+/// Drops repeated chunks, keeping the first occurrence. Exact text repeats are caught by
+/// hash before embedding; near-duplicates are caught by comparing each new embedding
+/// against the ones already kept.
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    threshold: f64,
+    seen_text: HashSet<u64>,
+    kept: Vec<Vec<f64>>,
+    removed: usize,
+}
+
+impl Deduplicator {
+    /// `threshold` is the cosine similarity above which an embedding counts as a duplicate.
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            seen_text: HashSet::new(),
+            kept: Vec::new(),
+            removed: 0,
+        }
+    }
+
+    pub fn is_new_text(&mut self, text: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        text.trim().hash(&mut hasher);
+        let is_new = self.seen_text.insert(hasher.finish());
+        if !is_new {
+            self.removed += 1;
+        }
+        is_new
+    }
+
+    /// Fails if `embedding` does not have the same length as the ones already kept.
+    pub fn is_new_embedding(&mut self, embedding: &[f64]) -> Result<bool> {
+        for kept in &self.kept {
+            if cosine_similarity(kept, embedding)? > self.threshold {
+                self.removed += 1;
+                return Ok(false);
+            }
+        }
+        self.kept.push(embedding.to_vec());
+        Ok(true)
+    }
+
+    pub fn removed(&self) -> usize {
+        self.removed
+    }
+}
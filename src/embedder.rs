@@ -0,0 +1,168 @@
+use crate::{CipherError, EmbeddingConfig, Result, RetryPolicy};
+use futures_util::future::BoxFuture;
+use ollama_rs::generation::options::GenerationOptions;
+use ollama_rs::Ollama;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+pub const DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+pub const DEFAULT_OPENAI_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+/// How long one request to an OpenAI-compatible server may take before it is retried.
+pub const DEFAULT_OPENAI_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Turns text into an embedding vector. Implemented for Ollama and OpenAI-compatible
+/// APIs, and easy to fake in tests.
+pub trait Embedder: Send + Sync {
+    /// Name of the model, recorded with each embedding.
+    fn model(&self) -> &str;
+
+    /// Identifies the service and model in the embedding cache, so the same model name
+    /// served by different providers or servers never shares entries.
+    fn cache_key(&self) -> String {
+        self.model().to_string()
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f64>>>;
+}
+
+pub struct OllamaEmbedder {
+    ollama: Ollama,
+    model: String,
+    retry: RetryPolicy,
+}
+
+impl OllamaEmbedder {
+    pub fn new(config: &EmbeddingConfig) -> Self {
+        Self {
+            ollama: config.ollama(),
+            model: config.model.clone(),
+            retry: config.retry,
+        }
+    }
+}
+
+impl Embedder for OllamaEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn cache_key(&self) -> String {
+        format!("ollama {} {}", self.ollama.uri(), self.model)
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f64>>> {
+        Box::pin(async move {
+            let options = GenerationOptions::default();
+            let res = self
+                .retry
+                .run(|| {
+                    self.ollama
+                        .generate_embeddings(self.model.clone(), text.to_string(), Some(options.clone()))
+                })
+                .await
+                .map_err(|e| {
+                    let message = e.to_string();
+                    // ollama-rs only hands back strings; a failed send is reqwest's "error sending request".
+                    if message.contains("error sending request") {
+                        CipherError::Ollama {
+                            uri: self.ollama.uri(),
+                            message,
+                        }
+                    } else {
+                        CipherError::Embedding(message)
+                    }
+                })?;
+            Ok(res.embeddings)
+        })
+    }
+}
+
+/// Embeds through the `/embeddings` endpoint of OpenAI or any compatible server. The base
+/// URL includes the API version, as in `OPENAI_BASE_URL`.
+pub struct OpenAiEmbedder {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    retry: RetryPolicy,
+}
+
+impl OpenAiEmbedder {
+    pub fn new(base_url: impl Into<String>, api_key: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            client: http_client(DEFAULT_OPENAI_TIMEOUT),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: api_key.into(),
+            model: model.into(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Reads the key from `OPENAI_API_KEY` and the server from `OPENAI_BASE_URL`, which
+    /// defaults to `https://api.openai.com/v1`.
+    pub fn from_env(model: impl Into<String>) -> Result<Self> {
+        let api_key = std::env::var("OPENAI_API_KEY")
+            .map_err(|_| CipherError::Config("OPENAI_API_KEY is not set".to_string()))?;
+        let base_url = std::env::var("OPENAI_BASE_URL").unwrap_or_else(|_| DEFAULT_OPENAI_BASE_URL.to_string());
+        Ok(Self::new(base_url, api_key, model))
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = http_client(timeout);
+        self
+    }
+
+    async fn request(&self, text: &str) -> std::result::Result<Vec<f64>, String> {
+        let body = json!({ "model": self.model, "input": text });
+        let res = self
+            .client
+            .post(format!("{}/embeddings", self.base_url))
+            .bearer_auth(&self.api_key)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = res.status();
+        let body = res.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("{}: {}", status, body));
+        }
+        let body: Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+        body["data"][0]["embedding"]
+            .as_array()
+            .and_then(|embedding| embedding.iter().map(Value::as_f64).collect())
+            .ok_or_else(|| "response has no embedding".to_string())
+    }
+}
+
+impl Embedder for OpenAiEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn cache_key(&self) -> String {
+        format!("openai {} {}", self.base_url, self.model)
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f64>>> {
+        Box::pin(async move {
+            self.retry
+                .run(|| self.request(text))
+                .await
+                .map_err(CipherError::Embedding)
+        })
+    }
+}
+
+fn http_client(timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .expect("the HTTP client's TLS backend failed to initialize")
+}
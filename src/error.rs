@@ -0,0 +1,82 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+pub type Result<T, E = CipherError> = std::result::Result<T, E>;
+
+/// Everything the library can fail with, so callers can tell a missing file from an
+/// unreachable Ollama without parsing messages.
+#[derive(Debug)]
+pub enum CipherError {
+    /// Reading or writing a file or directory failed.
+    File { path: PathBuf, source: io::Error },
+    /// Any other I/O failure, e.g. writing out an embedding from a callback.
+    Io(io::Error),
+    /// The EPUB could not be opened or parsed.
+    Epub { path: PathBuf, message: String },
+    UnsupportedFile(PathBuf),
+    UnknownHtmlBackend(String),
+    /// Ollama could not be reached, e.g. the connection was refused or timed out.
+    Ollama { uri: String, message: String },
+    /// The embedding service answered but did not produce an embedding, e.g. because the
+    /// model is not installed or the response was malformed.
+    Embedding(String),
+    /// A required setting is missing or invalid, e.g. an unset API key.
+    Config(String),
+    /// The file was read but holds nothing to embed, e.g. an EPUB of scanned pages.
+    NoText(PathBuf),
+    /// Two embeddings that should come from the same model have different lengths.
+    DimensionMismatch { expected: usize, actual: usize },
+}
+
+impl CipherError {
+    pub(crate) fn file(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Self::File {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub(crate) fn epub(path: impl Into<PathBuf>, error: impl fmt::Display) -> Self {
+        Self::Epub {
+            path: path.into(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CipherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File { path, source } => write!(f, "Failed to access {}: {}", path.display(), source),
+            Self::Io(source) => write!(f, "I/O error: {}", source),
+            Self::Epub { path, message } => write!(f, "Failed to open EPUB file {}: {}", path.display(), message),
+            Self::UnsupportedFile(path) => write!(f, "Unsupported file type: {}", path.display()),
+            Self::UnknownHtmlBackend(name) => {
+                write!(f, "Unknown HTML backend '{}', expected 'html2md' or 'prose'", name)
+            }
+            Self::Ollama { uri, message } => write!(f, "Failed to reach Ollama at {}: {}", uri, message),
+            Self::Embedding(message) => write!(f, "Failed to generate embedding: {}", message),
+            Self::Config(message) => write!(f, "Configuration error: {}", message),
+            Self::NoText(path) => write!(f, "No extractable text found in {}", path.display()),
+            Self::DimensionMismatch { expected, actual } => {
+                write!(f, "Embedding has dimension {}, expected {}", actual, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CipherError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::File { source, .. } | Self::Io(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CipherError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
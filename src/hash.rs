@@ -0,0 +1,13 @@
+/// FNV-1a over the parts separated by NUL bytes. Unlike `DefaultHasher` it is stable
+/// across Rust releases, so it can be persisted.
+pub(crate) fn stable_hash(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for (idx, part) in parts.iter().enumerate() {
+        let separator: &[u8] = if idx == 0 { &[] } else { &[0] };
+        for &byte in separator.iter().chain(part.as_bytes()) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
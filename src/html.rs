@@ -0,0 +1,288 @@
+use crate::CipherError;
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::str::FromStr;
+
+/// Converts the HTML of a single EPUB resource into markdown.
+pub trait HtmlToMarkdown {
+    fn convert(&self, html: &str) -> String;
+}
+
+/// The default converter, backed by `html2md`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Html2Md;
+
+impl HtmlToMarkdown for Html2Md {
+    fn convert(&self, html: &str) -> String {
+        html2md::parse_html(html)
+    }
+}
+
+/// A converter tuned for prose. It keeps headings, paragraphs, list items,
+/// quotes and tables as separate blocks and drops everything else. Text sitting
+/// directly in a container such as `<div>` becomes a paragraph of its own.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProseExtractor;
+
+impl HtmlToMarkdown for ProseExtractor {
+    fn convert(&self, html: &str) -> String {
+        let dom = parse_document(RcDom::default(), Default::default()).one(html);
+        let mut blocks = Vec::new();
+        collect_blocks(&dom.document, &mut blocks);
+        blocks.join("\n\n")
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum HtmlBackend {
+    #[default]
+    #[value(name = "html2md")]
+    Html2Md,
+    Prose,
+}
+
+impl HtmlToMarkdown for HtmlBackend {
+    fn convert(&self, html: &str) -> String {
+        match self {
+            HtmlBackend::Html2Md => Html2Md.convert(html),
+            HtmlBackend::Prose => ProseExtractor.convert(html),
+        }
+    }
+}
+
+impl FromStr for HtmlBackend {
+    type Err = CipherError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html2md" => Ok(HtmlBackend::Html2Md),
+            "prose" => Ok(HtmlBackend::Prose),
+            other => Err(CipherError::UnknownHtmlBackend(other.to_string())),
+        }
+    }
+}
+
+/// Elements that flow within a line of text rather than starting a block.
+const INLINE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "br", "cite", "code", "del", "dfn", "em", "font", "i", "img", "ins", "kbd", "mark",
+    "q", "s", "samp", "small", "span", "strong", "sub", "sup", "time", "u", "var", "wbr",
+];
+
+fn collect_blocks(node: &Handle, blocks: &mut Vec<String>) {
+    if let NodeData::Element { ref name, .. } = node.data {
+        let tag = name.local.as_ref();
+        let block = match tag {
+            "head" | "script" | "style" | "nav" => return,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = tag[1..].parse::<usize>().unwrap_or(1);
+                Some(format!("{} {}", "#".repeat(level), element_text(node)))
+            }
+            "p" | "dt" | "dd" | "figcaption" => Some(element_text(node)),
+            "li" => Some(format!("- {}", element_text(node))),
+            "blockquote" => Some(format!("> {}", element_text(node))),
+            "pre" => Some(format!("```\n{}\n```", raw_text(node).trim_end())),
+            "table" => Some(table_text(node)),
+            _ => None,
+        };
+        if let Some(block) = block {
+            if !block.trim().is_empty() {
+                blocks.push(block);
+            }
+            return;
+        }
+    }
+
+    // Inline content between blocks forms a paragraph of its own.
+    let mut run = String::new();
+    for child in node.children.borrow().iter() {
+        if is_inline(child) {
+            append_text(child, &mut run);
+        } else {
+            push_run(&mut run, blocks);
+            collect_blocks(child, blocks);
+        }
+    }
+    push_run(&mut run, blocks);
+}
+
+fn is_inline(node: &Handle) -> bool {
+    match node.data {
+        NodeData::Text { .. } | NodeData::Comment { .. } => true,
+        NodeData::Element { ref name, .. } => {
+            INLINE_TAGS.contains(&name.local.as_ref()) && node.children.borrow().iter().all(is_inline)
+        }
+        _ => false,
+    }
+}
+
+fn push_run(run: &mut String, blocks: &mut Vec<String>) {
+    let text = run.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !text.is_empty() {
+        blocks.push(text);
+    }
+    run.clear();
+}
+
+fn element_text(node: &Handle) -> String {
+    raw_text(node).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn raw_text(node: &Handle) -> String {
+    let mut text = String::new();
+    append_text(node, &mut text);
+    text
+}
+
+fn append_text(node: &Handle, text: &mut String) {
+    match node.data {
+        NodeData::Text { ref contents } => text.push_str(&contents.borrow()),
+        NodeData::Element { ref name, .. } if name.local.as_ref() == "br" => text.push('\n'),
+        _ => {
+            for child in node.children.borrow().iter() {
+                append_text(child, text);
+            }
+        }
+    }
+}
+
+fn table_text(table: &Handle) -> String {
+    let mut rows = Vec::new();
+    collect_rows(table, &mut rows);
+
+    let mut lines = Vec::new();
+    for (idx, row) in rows.iter().enumerate() {
+        lines.push(format!("| {} |", row.join(" | ")));
+        if idx == 0 {
+            lines.push(format!("|{}", " --- |".repeat(row.len())));
+        }
+    }
+    lines.join("\n")
+}
+
+fn is_element(node: &Handle, tags: &[&str]) -> bool {
+    match node.data {
+        NodeData::Element { ref name, .. } => tags.contains(&name.local.as_ref()),
+        _ => false,
+    }
+}
+
+fn collect_rows(node: &Handle, rows: &mut Vec<Vec<String>>) {
+    for child in node.children.borrow().iter() {
+        if !is_element(child, &["tr"]) {
+            collect_rows(child, rows);
+            continue;
+        }
+        let cells: Vec<String> = child
+            .children
+            .borrow()
+            .iter()
+            .filter(|cell| is_element(cell, &["td", "th"]))
+            .map(element_text)
+            .collect();
+        if !cells.is_empty() {
+            rows.push(cells);
+        }
+    }
+}
+
+/// Finds `<img>` elements that carry a caption and returns `(src, caption)` pairs.
+/// The caption is taken from the alt or title attribute, or failing that from a
+/// `<figcaption>` or caption-classed element in the image's enclosing figure block.
+pub(crate) fn captioned_images(html: &str) -> Vec<(String, String)> {
+    let dom = parse_document(RcDom::default(), Default::default()).one(html);
+    let mut images = Vec::new();
+    let mut ancestors = Vec::new();
+    collect_images(&dom.document, &mut ancestors, &mut images);
+    images
+}
+
+fn collect_images(node: &Handle, ancestors: &mut Vec<Handle>, images: &mut Vec<(String, String)>) {
+    if is_element(node, &["img"]) {
+        if let Some(src) = attribute(node, "src") {
+            let caption = ["alt", "title"]
+                .iter()
+                .filter_map(|name| attribute(node, name))
+                .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+                .find(|text| !text.is_empty())
+                .or_else(|| {
+                    ancestors
+                        .iter()
+                        .rev()
+                        .take(2)
+                        .take_while(|ancestor| !is_element(ancestor, &["body", "section", "article"]))
+                        .find_map(find_caption)
+                });
+            if let Some(caption) = caption {
+                images.push((src, caption));
+            }
+        }
+        return;
+    }
+
+    ancestors.push(node.clone());
+    for child in node.children.borrow().iter() {
+        collect_images(child, ancestors, images);
+    }
+    ancestors.pop();
+}
+
+/// Returns the `(href, title)` links of an EPUB3 navigation document's table of
+/// contents, i.e. the `<nav epub:type="toc">` element, in document order.
+pub(crate) fn toc_links(html: &str) -> Vec<(String, String)> {
+    let dom = parse_document(RcDom::default(), Default::default()).one(html);
+    let mut links = Vec::new();
+    if let Some(nav) = find_toc_nav(&dom.document) {
+        collect_links(&nav, &mut links);
+    }
+    links
+}
+
+fn find_toc_nav(node: &Handle) -> Option<Handle> {
+    let is_toc = is_element(node, &["nav"])
+        && attribute(node, "epub:type").is_some_and(|kind| kind.split_whitespace().any(|kind| kind == "toc"));
+    if is_toc {
+        return Some(node.clone());
+    }
+    node.children.borrow().iter().find_map(find_toc_nav)
+}
+
+fn collect_links(node: &Handle, links: &mut Vec<(String, String)>) {
+    if is_element(node, &["a"]) {
+        if let Some(href) = attribute(node, "href") {
+            links.push((href, element_text(node)));
+        }
+        return;
+    }
+    for child in node.children.borrow().iter() {
+        collect_links(child, links);
+    }
+}
+
+fn find_caption(node: &Handle) -> Option<String> {
+    for child in node.children.borrow().iter() {
+        let is_caption = is_element(child, &["figcaption"])
+            || attribute(child, "class").is_some_and(|class| class.split_whitespace().any(|c| c == "caption"));
+        if is_caption {
+            let text = element_text(child);
+            if !text.is_empty() {
+                return Some(text);
+            }
+        }
+        if let Some(caption) = find_caption(child) {
+            return Some(caption);
+        }
+    }
+    None
+}
+
+fn attribute(node: &Handle, name: &str) -> Option<String> {
+    match node.data {
+        NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|attr| attr.name.local.as_ref() == name)
+            .map(|attr| attr.value.to_string()),
+        _ => None,
+    }
+}
@@ -1,45 +1,734 @@
-use anyhow::{Result};
 use epub::doc::EpubDoc;
-use std::path::Path;
+use futures_util::{future, stream, StreamExt};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
 use ollama_rs::Ollama;
-use ollama_rs::generation::options::GenerationOptions;
+
+pub mod boilerplate;
+pub mod cache;
+pub mod chunk;
+pub mod dedup;
+pub mod embedder;
+pub mod error;
+mod hash;
+pub mod html;
+pub mod normalize;
+pub mod progress;
+pub mod retry;
+pub mod similarity;
+
+pub use boilerplate::BoilerplateFilter;
+pub use cache::{default_cache_dir, EmbeddingCache};
+pub use chunk::{chunk_markdown, chunk_markdown_with_headings, Chunk, ChunkOptions};
+pub use dedup::Deduplicator;
+pub use embedder::{Embedder, OllamaEmbedder, OpenAiEmbedder, DEFAULT_OPENAI_BASE_URL, DEFAULT_OPENAI_EMBEDDING_MODEL, DEFAULT_OPENAI_TIMEOUT};
+pub use error::{CipherError, Result};
+pub use html::{Html2Md, HtmlBackend, HtmlToMarkdown, ProseExtractor};
+pub use normalize::normalize_markdown;
+pub use progress::{EtaEstimator, Progress};
+pub use retry::RetryPolicy;
+pub use similarity::{cosine_similarity, dot_product, euclidean_distance, l2_normalize};
+
+pub const DEFAULT_LARGE_RESOURCE_BYTES: usize = 2 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    /// Resources larger than this are logged and converted in `</p>`-aligned segments.
+    pub large_resource_bytes: usize,
+    /// Split each resource into sized chunks. When unset each resource is one chunk.
+    pub chunking: Option<ChunkOptions>,
+    /// Leave out front and back matter such as licenses. Off by default.
+    pub boilerplate: Option<BoilerplateFilter>,
+    /// Run [`normalize_markdown`] over the text before chunking. Off by default.
+    pub normalize: bool,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            large_resource_bytes: DEFAULT_LARGE_RESOURCE_BYTES,
+            chunking: None,
+            boilerplate: None,
+            normalize: false,
+        }
+    }
+}
 
 pub fn epub_to_markdown(path_str: &str) -> Result<Vec<String>> {
+    epub_to_markdown_with(path_str, &Html2Md, &ExtractOptions::default())
+}
+
+pub fn epub_to_markdown_with(
+    path_str: &str,
+    converter: &dyn HtmlToMarkdown,
+    options: &ExtractOptions,
+) -> Result<Vec<String>> {
+    let chunks = epub_chunks(path_str, converter, options)?;
+    Ok(chunks.into_iter().map(|(chunk, _)| chunk).collect())
+}
+
+pub type ChunkMetadata = HashMap<String, String>;
+
+/// An id derived from the chunk itself, so rebuilding from the same document gives the
+/// same ids. `source` is whatever identifies the document, e.g. its file name.
+pub fn content_chunk_id(source: &str, chunk_index: usize, content: &str) -> String {
+    format!("{:016x}", hash::stable_hash(&[source, &chunk_index.to_string(), content]))
+}
+
+/// Like [`epub_to_markdown`], but tags each chunk with the `spine_id` and `chapter_index`
+/// (position in the spine) of the resource it came from.
+pub fn epub_to_markdown_with_metadata(path_str: &str) -> Result<Vec<(String, ChunkMetadata)>> {
+    epub_chunks(path_str, &Html2Md, &ExtractOptions::default())
+}
+
+pub fn epub_chunks(
+    path_str: &str,
+    converter: &dyn HtmlToMarkdown,
+    options: &ExtractOptions,
+) -> Result<Vec<(String, ChunkMetadata)>> {
     let path = Path::new(path_str);
-    let mut doc = EpubDoc::new(path).map_err(|e| anyhow::anyhow!("Failed to open EPUB file: {}", e))?;
+    let mut doc = EpubDoc::new(path).map_err(|e| CipherError::epub(path, e))?;
 
     let mut markdown_chunks = Vec::new();
+    let toc = toc_entries(&mut doc);
+    let mut toc_title: Option<String> = None;
+    let mut boilerplate = boilerplate::BoilerplateState::default();
 
-    let spine_ids: Vec<String> = doc.spine.iter().cloned().collect();
-    for (_idx, spine_item_id) in spine_ids.iter().enumerate() {
+    let spine_ids = doc.spine.to_vec();
+    for (chapter_index, spine_item_id) in spine_ids.iter().enumerate() {
+        // Spine items without a TOC entry of their own belong to the nearest one before them.
+        let mut has_toc_entry = false;
+        if let Some((item_path, _)) = doc.resources.get(spine_item_id) {
+            let item_path = item_path.to_string_lossy();
+            if let Some(entry) = toc.iter().find(|entry| entry.resource() == item_path) {
+                toc_title = Some(entry.title.clone());
+                has_toc_entry = true;
+            }
+        }
         if let Ok(content_bytes_vec) = doc.get_resource(spine_item_id) {
             let html_content = String::from_utf8_lossy(&content_bytes_vec);
-            let markdown = html2md::parse_html(&html_content);
-            markdown_chunks.push(markdown);
+            let markdown = if html_content.len() > options.large_resource_bytes {
+                eprintln!(
+                    "Resource '{}' is {} bytes (limit {}), converting it in segments",
+                    spine_item_id,
+                    html_content.len(),
+                    options.large_resource_bytes
+                );
+                segment_html(&html_content, options.large_resource_bytes)
+                    .into_iter()
+                    .map(|segment| converter.convert(segment))
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            } else {
+                converter.convert(&html_content)
+            };
+            let markdown = if options.normalize { normalize_markdown(&markdown) } else { markdown };
+            // Only skip by title when the title is the item's own, so a skipped copyright page
+            // does not take the untitled chapters after it along.
+            let skipped_title = has_toc_entry && toc_title.as_deref().is_some_and(|title| {
+                options.boilerplate.as_ref().is_some_and(|filter| filter.skips_title(title))
+            });
+            let markdown = match &options.boilerplate {
+                Some(_) if skipped_title => String::new(),
+                Some(filter) => boilerplate.trim(filter, &markdown).to_string(),
+                None => markdown,
+            };
+            if markdown.trim().is_empty() && options.boilerplate.is_some() {
+                continue;
+            }
+            let mut metadata = ChunkMetadata::from([
+                ("spine_id".to_string(), spine_item_id.clone()),
+                ("chapter_index".to_string(), chapter_index.to_string()),
+            ]);
+            if let Some(title) = &toc_title {
+                metadata.insert("toc_title".to_string(), title.clone());
+            }
+            let chunks = match options.chunking {
+                Some(chunking) => chunk_markdown_with_headings(&markdown, &chunking),
+                None => vec![Chunk {
+                    headings: chunk::leading_headings(&markdown),
+                    text: markdown,
+                }],
+            };
+            for chunk in chunks {
+                let metadata = with_headings(metadata.clone(), &chunk.headings);
+                markdown_chunks.push((chunk.text, metadata));
+            }
         }
     }
 
     Ok(markdown_chunks)
 }
 
-pub async fn get_embeddings(markdown_chunks: Vec<String>) -> Result<Vec<Vec<f64>>> {
-    let ollama = Ollama::default();
-    let mut embeddings = Vec::new();
-    let options = GenerationOptions::default();
+/// Adds the innermost heading and the full heading path of a chunk to its metadata.
+fn with_headings(mut metadata: ChunkMetadata, headings: &[String]) -> ChunkMetadata {
+    if let Some(heading) = headings.last() {
+        metadata.insert("heading".to_string(), heading.clone());
+        metadata.insert("section".to_string(), headings.join(" > "));
+    }
+    metadata
+}
 
-    for chunk in markdown_chunks {
-        if chunk.trim().is_empty() {
-            continue;
+/// Chunks a Markdown or plain-text file. Without chunking options the default sizes are
+/// used, since unlike an EPUB there are no chapters to split on. Chunks carry the same
+/// `heading` and `section` metadata as those of [`epub_chunks`]. A boilerplate filter
+/// trims the text to what lies between its start and end markers.
+pub fn text_to_chunks(path_str: &str, options: &ExtractOptions) -> Result<Vec<(String, ChunkMetadata)>> {
+    let text = std::fs::read_to_string(path_str).map_err(|e| CipherError::file(path_str, e))?;
+    let text = if options.normalize { normalize_markdown(&text) } else { text };
+    let text = match &options.boilerplate {
+        Some(filter) => boilerplate::BoilerplateState::default().trim(filter, &text).to_string(),
+        None => text,
+    };
+    let chunking = options.chunking.unwrap_or_default();
+    Ok(chunk_markdown_with_headings(&text, &chunking)
+        .into_iter()
+        .map(|chunk| {
+            let metadata = with_headings(ChunkMetadata::new(), &chunk.headings);
+            (chunk.text, metadata)
+        })
+        .collect())
+}
+
+/// Extracts chunks from an EPUB, Markdown or plain-text file, picked by file extension.
+/// A file without any text, e.g. an image-only EPUB, is a [`CipherError::NoText`].
+pub fn file_chunks(
+    path_str: &str,
+    converter: &dyn HtmlToMarkdown,
+    options: &ExtractOptions,
+) -> Result<Vec<(String, ChunkMetadata)>> {
+    let extension = Path::new(path_str)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let chunks = match extension.as_str() {
+        "epub" => epub_chunks(path_str, converter, options)?,
+        "md" | "markdown" | "txt" => text_to_chunks(path_str, options)?,
+        _ => return Err(CipherError::UnsupportedFile(path_str.into())),
+    };
+    if !chunks.iter().any(|(chunk, _)| has_text(chunk)) {
+        return Err(CipherError::NoText(path_str.into()));
+    }
+    Ok(chunks)
+}
+
+/// Whether `markdown` has any letters or digits outside image references like `![](cover.png)`.
+fn has_text(markdown: &str) -> bool {
+    let mut rest = markdown;
+    while let Some(start) = rest.find("![") {
+        if rest[..start].chars().any(char::is_alphanumeric) {
+            return true;
+        }
+        match rest[start..].find(')') {
+            Some(end) => rest = &rest[start + end + 1..],
+            None => break,
         }
+    }
+    rest.chars().any(char::is_alphanumeric)
+}
 
-        let res = ollama.generate_embeddings("mxbai-embed-large".to_string(), chunk.to_string(), Some(options.clone())).await;
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["epub", "md", "markdown", "txt"];
+
+/// Recursively lists the files under `dir` that [`file_chunks`] can read, sorted by path.
+/// With a `glob`, only files whose name matches it are kept; `*` and `?` are wildcards.
+/// Symlinked directories are not followed, so link cycles cannot loop forever, and
+/// subdirectories that cannot be read are logged and skipped.
+pub fn supported_files(dir: &Path, glob: Option<&str>) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let entries = match std::fs::read_dir(&current) {
+            Ok(entries) => entries,
+            Err(e) if current == dir => return Err(CipherError::file(&current, e)),
+            Err(e) => {
+                eprintln!("Skipping {}: {}", current.display(), e);
+                continue;
+            }
+        };
+        for entry in entries {
+            let (path, file_type) = match entry.and_then(|entry| Ok((entry.path(), entry.file_type()?))) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    eprintln!("Skipping an entry of {}: {}", current.display(), e);
+                    continue;
+                }
+            };
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            // Links to files are indexed like the files themselves.
+            if file_type.is_symlink() && !path.is_file() {
+                continue;
+            }
+            let supported = path.extension().is_some_and(|ext| {
+                SUPPORTED_EXTENSIONS
+                    .iter()
+                    .any(|supported| ext.eq_ignore_ascii_case(supported))
+            });
+            let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+            if supported && glob.is_none_or(|glob| glob_match(glob, &name)) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
 
-        if let Ok(res) = res {
-            embeddings.push(res.embeddings);
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Classic backtracking match, remembering only the most recent `*`.
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
         } else {
-            eprintln!("Failed to generate embeddings: {:?}", res);
+            return false;
         }
     }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn segment_html(html: &str, max_bytes: usize) -> Vec<&str> {
+    const BOUNDARY: &str = "</p>";
+
+    let mut segments = Vec::new();
+    let mut rest = html;
+    while rest.len() > max_bytes {
+        let mut limit = max_bytes;
+        while !rest.is_char_boundary(limit) {
+            limit -= 1;
+        }
+        let cut = rest[..limit]
+            .rfind(BOUNDARY)
+            .or_else(|| rest[limit..].find(BOUNDARY).map(|idx| limit + idx))
+            .map(|idx| idx + BOUNDARY.len());
+        match cut {
+            Some(cut) if cut < rest.len() => {
+                segments.push(&rest[..cut]);
+                rest = &rest[cut..];
+            }
+            _ => break,
+        }
+    }
+    segments.push(rest);
+    segments
+}
+
+/// One entry of a book's table of contents. `href` is the resource path inside the
+/// EPUB, with the `#fragment` if the entry points into the middle of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    pub title: String,
+    pub href: String,
+    pub order: usize,
+}
 
+impl TocEntry {
+    /// The resource path without the fragment.
+    pub fn resource(&self) -> &str {
+        self.href.split('#').next().unwrap_or_default()
+    }
+}
+
+/// Reads the table of contents from the EPUB2 NCX, or failing that from the EPUB3
+/// navigation document. Nested entries are flattened in reading order; a book with
+/// neither gives an empty list.
+pub fn epub_toc(path_str: &str) -> Result<Vec<TocEntry>> {
+    let mut doc = EpubDoc::new(Path::new(path_str)).map_err(|e| CipherError::epub(path_str, e))?;
+    Ok(toc_entries(&mut doc))
+}
+
+fn toc_entries<R: std::io::Read + std::io::Seek>(doc: &mut EpubDoc<R>) -> Vec<TocEntry> {
+    fn flatten(nav_points: &[epub::doc::NavPoint], entries: &mut Vec<TocEntry>) {
+        for nav_point in nav_points {
+            entries.push(TocEntry {
+                title: nav_point.label.trim().to_string(),
+                href: nav_point.content.to_string_lossy().into_owned(),
+                order: nav_point.play_order,
+            });
+            flatten(&nav_point.children, entries);
+        }
+    }
+
+    let mut entries = Vec::new();
+    flatten(&doc.toc, &mut entries);
+    if !entries.is_empty() {
+        entries.sort_by_key(|entry| entry.order);
+        return entries;
+    }
+
+    let mut documents: Vec<(String, PathBuf)> = doc
+        .resources
+        .iter()
+        .filter(|(_, (_, mime))| mime == "application/xhtml+xml")
+        .map(|(id, (path, _))| (id.clone(), path.clone()))
+        .collect();
+    documents.sort();
+    for (id, nav_path) in documents {
+        let Ok(content) = doc.get_resource(&id) else {
+            continue;
+        };
+        let html = String::from_utf8_lossy(&content);
+        if !html.contains("<nav") {
+            continue;
+        }
+        let links = html::toc_links(&html);
+        if links.is_empty() {
+            continue;
+        }
+        return links
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (href, title))| {
+                let (file, fragment) = href.split_once('#').unwrap_or((&href, ""));
+                let mut href = resolve_resource_path(&nav_path, file).to_string_lossy().into_owned();
+                if !fragment.is_empty() {
+                    href = format!("{}#{}", href, fragment);
+                }
+                TocEntry {
+                    title,
+                    href,
+                    order: idx + 1,
+                }
+            })
+            .collect();
+    }
+    Vec::new()
+}
+
+pub fn epub_title(path_str: &str) -> Result<Option<String>> {
+    let doc = EpubDoc::new(Path::new(path_str)).map_err(|e| CipherError::epub(path_str, e))?;
+    Ok(doc.mdata("title").map(|title| title.trim().to_string()).filter(|title| !title.is_empty()))
+}
+
+/// Describes where a chunk comes from, e.g. `From 'House Rats and Mice', TRAPS: `.
+pub fn contextual_prefix(title: &str, chapter: Option<&str>) -> String {
+    match chapter.map(|chapter| chapter.trim().trim_end_matches(['.', ':'])) {
+        Some(chapter) if !chapter.is_empty() => format!("From '{}', {}: ", title, chapter),
+        _ => format!("From '{}': ", title),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Figure {
+    pub resource: String,
+    pub caption: String,
+}
+
+impl Figure {
+    pub fn to_markdown(&self) -> String {
+        format!("![{}]({})", self.caption, self.resource)
+    }
+
+    pub fn metadata(&self) -> ChunkMetadata {
+        ChunkMetadata::from([
+            ("type".to_string(), "figure".to_string()),
+            ("resource".to_string(), self.resource.clone()),
+        ])
+    }
+}
+
+pub fn epub_figures(path_str: &str) -> Result<Vec<Figure>> {
+    let path = Path::new(path_str);
+    let mut doc = EpubDoc::new(path).map_err(|e| CipherError::epub(path, e))?;
+
+    let mut figures: Vec<Figure> = Vec::new();
+
+    let spine_ids = doc.spine.to_vec();
+    for spine_item_id in &spine_ids {
+        let Some((item_path, _)) = doc.resources.get(spine_item_id).cloned() else {
+            continue;
+        };
+        let Ok(content_bytes_vec) = doc.get_resource(spine_item_id) else {
+            continue;
+        };
+        let html_content = String::from_utf8_lossy(&content_bytes_vec);
+        for (src, caption) in html::captioned_images(&html_content) {
+            let src = src.split(['#', '?']).next().unwrap_or_default();
+            let resource = resolve_resource_path(&item_path, src);
+            let exists = doc.resources.values().any(|(res_path, _)| *res_path == resource);
+            let resource = resource.to_string_lossy().into_owned();
+            if exists && !figures.iter().any(|figure| figure.resource == resource) {
+                figures.push(Figure { resource, caption });
+            }
+        }
+    }
+
+    Ok(figures)
+}
+
+fn resolve_resource_path(item_path: &Path, src: &str) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in item_path.parent().unwrap_or(Path::new("")).join(src).components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(part) => resolved.push(part),
+            _ => {}
+        }
+    }
+    resolved
+}
+
+pub const DEFAULT_EMBEDDING_MODEL: &str = "mxbai-embed-large";
+
+pub const DEFAULT_OLLAMA_HOST: &str = "http://127.0.0.1";
+pub const DEFAULT_OLLAMA_PORT: u16 = 11434;
+pub const DEFAULT_EMBEDDING_CONCURRENCY: usize = 4;
+
+/// Which Ollama instance and model to embed with. Defaults to `mxbai-embed-large`
+/// on a local Ollama at `http://127.0.0.1:11434`, with 4 requests in flight, no cache, up
+/// to 3 attempts per request and embeddings left as the model returns them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddingConfig {
+    pub model: String,
+    pub host: String,
+    pub port: u16,
+    pub concurrency: usize,
+    pub cache: Option<EmbeddingCache>,
+    pub retry: RetryPolicy,
+    /// Scale every embedding to unit length before handing it out. The cache keeps the
+    /// model's own vectors either way.
+    pub normalize: bool,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            model: DEFAULT_EMBEDDING_MODEL.to_string(),
+            host: DEFAULT_OLLAMA_HOST.to_string(),
+            port: DEFAULT_OLLAMA_PORT,
+            concurrency: DEFAULT_EMBEDDING_CONCURRENCY,
+            cache: None,
+            retry: RetryPolicy::default(),
+            normalize: false,
+        }
+    }
+}
+
+impl EmbeddingConfig {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn with_host(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.host = host.into();
+        self.port = port;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: EmbeddingCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Builds a client for the configured instance. A host without a scheme gets `http://`.
+    pub fn ollama(&self) -> Ollama {
+        let host = if self.host.contains("://") {
+            self.host.clone()
+        } else {
+            format!("http://{}", self.host)
+        };
+        Ollama::new(host, self.port)
+    }
+}
+
+pub async fn list_local_models(config: &EmbeddingConfig) -> Result<Vec<String>> {
+    let ollama = config.ollama();
+    let models = ollama
+        .list_local_models()
+        .await
+        .map_err(|e| CipherError::Ollama {
+            uri: ollama.uri(),
+            message: e.to_string(),
+        })?;
+    Ok(models.into_iter().map(|model| model.name).collect())
+}
+
+pub async fn get_single_embedding(text: &str) -> Result<Vec<f64>> {
+    get_single_embedding_with(&EmbeddingConfig::default(), text).await
+}
+
+pub async fn get_single_embedding_with(config: &EmbeddingConfig, text: &str) -> Result<Vec<f64>> {
+    get_single_embedding_with_embedder(&OllamaEmbedder::new(config), text).await
+}
+
+/// Like [`get_single_embedding_with`], but embeds with any [`Embedder`].
+pub async fn get_single_embedding_with_embedder(embedder: &dyn Embedder, text: &str) -> Result<Vec<f64>> {
+    embedder.embed(text).await
+}
+
+pub async fn get_embeddings(markdown_chunks: Vec<String>) -> Result<Vec<Vec<f64>>> {
+    get_embeddings_with_progress(&EmbeddingConfig::default(), markdown_chunks, |_| {}).await
+}
+
+pub async fn get_embeddings_with_progress(
+    config: &EmbeddingConfig,
+    markdown_chunks: Vec<String>,
+    on_progress: impl FnMut(&Progress),
+) -> Result<Vec<Vec<f64>>> {
+    get_embeddings_with_embedder(&OllamaEmbedder::new(config), config, markdown_chunks, on_progress).await
+}
+
+/// Like [`get_embeddings_with_progress`], but embeds with any [`Embedder`]. Only the
+/// concurrency, cache and normalization settings of `config` are used.
+pub async fn get_embeddings_with_embedder(
+    embedder: &dyn Embedder,
+    config: &EmbeddingConfig,
+    markdown_chunks: Vec<String>,
+    mut on_progress: impl FnMut(&Progress),
+) -> Result<Vec<Vec<f64>>> {
+    let mut embeddings = Vec::new();
+    let on_progress = |progress: &Progress| {
+        on_progress(progress);
+        ControlFlow::Continue(())
+    };
+    stream_embeddings_with_embedder(embedder, config, markdown_chunks, None, on_progress, |_, _, embedding| {
+        embeddings.push(embedding);
+        Ok(())
+    })
+    .await?;
     Ok(embeddings)
 }
+
+/// Embeds each non-empty chunk and hands it to `on_embedding` as soon as it is ready,
+/// together with its index in `markdown_chunks`. Chunks that still fail to embed after
+/// the configured retries are logged and skipped; an error returned from `on_embedding`
+/// stops the run. So does an embedding whose length differs from the earlier ones, as a
+/// [`CipherError::DimensionMismatch`], since there is no telling which side is wrong.
+///
+/// When `prefixes` is set, the entry at a chunk's index is prepended to the text sent to
+/// the embedding model, while `on_embedding` still receives the original chunk content. Returning
+/// `ControlFlow::Break` from `on_progress` stops new requests from being sent; requests
+/// already in flight are still finished and handed to `on_embedding`.
+///
+/// With a cache configured, chunks already embedded by the same model are served from
+/// it without contacting Ollama, and new embeddings are written back.
+pub async fn stream_embeddings(
+    config: &EmbeddingConfig,
+    markdown_chunks: Vec<String>,
+    prefixes: Option<&[String]>,
+    on_progress: impl FnMut(&Progress) -> ControlFlow<()>,
+    on_embedding: impl FnMut(usize, String, Vec<f64>) -> Result<()>,
+) -> Result<()> {
+    let embedder = OllamaEmbedder::new(config);
+    stream_embeddings_with_embedder(&embedder, config, markdown_chunks, prefixes, on_progress, on_embedding).await
+}
+
+/// Like [`stream_embeddings`], but embeds with any [`Embedder`]. Only the concurrency,
+/// cache and normalization settings of `config` are used.
+pub async fn stream_embeddings_with_embedder(
+    embedder: &dyn Embedder,
+    config: &EmbeddingConfig,
+    markdown_chunks: Vec<String>,
+    prefixes: Option<&[String]>,
+    mut on_progress: impl FnMut(&Progress) -> ControlFlow<()>,
+    mut on_embedding: impl FnMut(usize, String, Vec<f64>) -> Result<()>,
+) -> Result<()> {
+    let cache = config.cache.as_ref();
+    let stopped = Cell::new(false);
+
+    let chunks: Vec<(usize, String)> = markdown_chunks
+        .into_iter()
+        .enumerate()
+        .filter(|(_, chunk)| !chunk.trim().is_empty())
+        .collect();
+    let total = chunks.len();
+    let mut estimator = EtaEstimator::default();
+
+    // `buffered` keeps up to `concurrency` requests in flight but yields them in input order.
+    let mut results = stream::iter(chunks)
+        .take_while(|_| future::ready(!stopped.get()))
+        .map(|(chunk_index, chunk)| {
+            let embed_text = match prefixes.and_then(|prefixes| prefixes.get(chunk_index)) {
+                Some(prefix) => format!("{}{}", prefix, chunk),
+                None => chunk.clone(),
+            };
+            let cached = cache.and_then(|cache| cache.get(&embedder.cache_key(), &embed_text));
+            async move {
+                let res = match cached {
+                    Some(embedding) => Ok((embedding, true)),
+                    None => embedder.embed(&embed_text).await.map(|embedding| (embedding, false)),
+                };
+                (chunk_index, chunk, embed_text, res)
+            }
+        })
+        .buffered(config.concurrency.max(1));
+
+    let mut done = 0;
+    let mut last_finished = Instant::now();
+    // Every embedding of a run must have the same length, or they cannot be compared later.
+    let mut dimension: Option<usize> = None;
+    while let Some((chunk_index, chunk, embed_text, res)) = results.next().await {
+        // Cache hits return at once; counting them would make the ETA far too optimistic.
+        let fetched = !matches!(res, Ok((_, true)));
+        match res {
+            Ok((mut embedding, from_cache)) => {
+                if let Some(expected) = dimension {
+                    similarity::check_dimensions(expected, embedding.len())?;
+                }
+                dimension = Some(embedding.len());
+                if let (Some(cache), false) = (cache, from_cache) {
+                    if let Err(e) = cache.put(&embedder.cache_key(), &embed_text, &embedding) {
+                        eprintln!("Failed to cache embedding: {:#}", e);
+                    }
+                }
+                if config.normalize {
+                    l2_normalize(&mut embedding);
+                }
+                on_embedding(chunk_index, chunk, embedding)?
+            }
+            Err(e) => eprintln!("Skipping chunk {}: {}", chunk_index, e),
+        }
+
+        if fetched {
+            estimator.record(last_finished.elapsed());
+        }
+        last_finished = Instant::now();
+        done += 1;
+        let progress = Progress {
+            done,
+            total,
+            eta: estimator.estimate(total - done),
+        };
+        if on_progress(&progress).is_break() {
+            stopped.set(true);
+        }
+    }
+
+    Ok(())
+}
@@ -1,20 +1,507 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use cipher::{epub_to_markdown, get_embeddings};
+use cipher::{
+    content_chunk_id, contextual_prefix, default_cache_dir, epub_figures, epub_title, file_chunks, list_local_models,
+    stream_embeddings_with_embedder, supported_files, BoilerplateFilter, ChunkMetadata, ChunkOptions, CipherError,
+    Deduplicator, Embedder, EmbeddingCache, EmbeddingConfig, ExtractOptions, HtmlBackend, OllamaEmbedder,
+    OpenAiEmbedder, RetryPolicy, DEFAULT_EMBEDDING_CONCURRENCY, DEFAULT_EMBEDDING_MODEL, DEFAULT_LARGE_RESOURCE_BYTES,
+    DEFAULT_OLLAMA_HOST, DEFAULT_OLLAMA_PORT, DEFAULT_OPENAI_EMBEDDING_MODEL,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(subcommand_negates_reqs = true)]
 struct Args {
-    epub_path: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// EPUB, Markdown or plain-text file to index, or a directory to search for them
+    #[clap(required = true)]
+    path: Option<String>,
+
+    /// Only index files in the directory whose name matches this pattern, e.g. `*.epub`
+    #[clap(long)]
+    glob: Option<String>,
+
+    /// Embedding service; `openai` reads OPENAI_API_KEY and optionally OPENAI_BASE_URL
+    #[clap(long, global = true, value_enum, default_value_t = Provider::Ollama)]
+    provider: Provider,
+
+    /// Model used to generate embeddings [default: mxbai-embed-large, or
+    /// text-embedding-3-small with `--provider openai`]
+    #[clap(long, global = true)]
+    embedding_model: Option<String>,
+
+    /// Ollama server to embed with; `http://` is assumed when no scheme is given
+    #[clap(long, global = true, default_value = DEFAULT_OLLAMA_HOST)]
+    ollama_host: String,
+
+    /// Port of the Ollama server
+    #[clap(long, global = true, default_value_t = DEFAULT_OLLAMA_PORT)]
+    ollama_port: u16,
+
+    /// Attempts per embedding request before giving up on transient failures
+    #[clap(long, global = true, default_value_t = RetryPolicy::default().max_attempts)]
+    max_attempts: usize,
+
+    /// Directory for cached embeddings [default: ~/.cache/cipher/embeddings]
+    #[clap(long, global = true)]
+    cache_dir: Option<String>,
+
+    /// Always call the embedding service, without reading or writing the embedding cache
+    #[clap(long, global = true, conflicts_with = "cache_dir")]
+    no_cache: bool,
+
+    /// Maximum number of embedding requests in flight at once
+    #[clap(long, default_value_t = DEFAULT_EMBEDDING_CONCURRENCY)]
+    concurrency: usize,
+
+    /// Scale each embedding to unit length, so dot product equals cosine similarity
+    #[clap(long)]
+    normalize_embeddings: bool,
+
+    /// How EPUB HTML is turned into Markdown: `html2md` converts everything, `prose` keeps
+    /// only headings, paragraphs, lists, quotes and tables
+    #[clap(long, value_enum, default_value_t = HtmlBackend::default())]
+    html_backend: HtmlBackend,
+
+    /// Also index each EPUB figure by its caption, with the image path in the `resource` metadata
+    #[clap(long)]
+    include_figures: bool,
+
+    /// EPUB chapters larger than this many bytes are converted in segments
+    #[clap(long, default_value_t = DEFAULT_LARGE_RESOURCE_BYTES)]
+    large_resource_bytes: usize,
+
+    /// Split each chapter into chunks of at most this many characters
+    #[clap(long)]
+    max_chars: Option<usize>,
+
+    /// Characters shared between consecutive chunks when --max-chars is set
+    #[clap(long, default_value_t = ChunkOptions::default().overlap, requires = "max_chars")]
+    overlap: usize,
+
+    /// Drop chunks with fewer than this many characters when --max-chars is set
+    #[clap(long, default_value_t = ChunkOptions::default().min_chars, requires = "max_chars")]
+    min_chars: usize,
+
+    /// Join chunks shorter than --min-chars onto their neighbours instead of dropping them
+    #[clap(long, requires = "max_chars")]
+    merge_short: bool,
+
+    /// Leave out Project Gutenberg headers, footers and license sections
+    #[clap(long)]
+    strip_boilerplate: bool,
+
+    /// Decode HTML entities and collapse repeated whitespace and blank lines before chunking
+    #[clap(long)]
+    normalize_whitespace: bool,
+
+    /// Prepend "From '<book title>', <chapter>: " to each chunk before embedding it
+    #[clap(long)]
+    contextual_prefix: bool,
+
+    /// Skip repeated chunks and those whose embedding is more than this cosine similarity to an earlier one
+    #[clap(long)]
+    dedup_threshold: Option<f64>,
+
+    /// How JSONL record ids are built: `position` gives `<file>#<chunk index>`, `content` a
+    /// hash of the file, chunk index and chunk text. Files in a directory are named by their
+    /// path relative to it, a single file by its name
+    #[clap(long, value_enum, default_value_t = IdScheme::Position)]
+    id_scheme: IdScheme,
+
+    /// Write one JSON object per embedded chunk to this file, or to stdout with `-`
+    #[clap(long)]
+    jsonl_out: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Provider {
+    Ollama,
+    Openai,
+}
+
+impl Provider {
+    fn default_model(self) -> &'static str {
+        match self {
+            Provider::Ollama => DEFAULT_EMBEDDING_MODEL,
+            Provider::Openai => DEFAULT_OPENAI_EMBEDDING_MODEL,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum IdScheme {
+    Position,
+    Content,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Embed a piece of text and print its dimension and norm
+    Embed {
+        /// Text to embed
+        #[clap(long, required_unless_present = "file", conflicts_with = "file")]
+        text: Option<String>,
+
+        /// Embed the whole contents of this file, as a single piece of text
+        #[clap(long)]
+        file: Option<String>,
+
+        /// Print the full embedding vector
+        #[clap(long)]
+        raw: bool,
+    },
+    /// Check that the embedding service and model are ready to use
+    Doctor {
+        /// Fail unless the embedding model returns vectors of this dimension
+        #[clap(long)]
+        expected_dim: Option<usize>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
-    let markdown_chunks = epub_to_markdown(&args.epub_path).context("Failed to convert EPUB to Markdown")?;
-    let embeddings = get_embeddings(markdown_chunks).await?;
-    for embedding in embeddings {
-        println!("Embedding for chunk: {:?}", embedding);
+    let model = args
+        .embedding_model
+        .clone()
+        .unwrap_or_else(|| args.provider.default_model().to_string());
+    let mut config = EmbeddingConfig::new(model)
+        .with_host(args.ollama_host.clone(), args.ollama_port)
+        .with_concurrency(args.concurrency)
+        .with_normalize(args.normalize_embeddings)
+        .with_retry(RetryPolicy {
+            max_attempts: args.max_attempts.max(1),
+            ..RetryPolicy::default()
+        });
+    if !args.no_cache {
+        if let Some(dir) = args.cache_dir.clone().map(PathBuf::from).or_else(default_cache_dir) {
+            config = config.with_cache(EmbeddingCache::new(dir));
+        }
+    }
+    // A missing API key is reported by `doctor` like any other failed check.
+    let embedder: cipher::Result<Box<dyn Embedder>> = match args.provider {
+        Provider::Ollama => Ok(Box::new(OllamaEmbedder::new(&config))),
+        Provider::Openai => OpenAiEmbedder::from_env(config.model.clone())
+            .map(|embedder| Box::new(embedder.with_retry(config.retry)) as Box<dyn Embedder>),
+    };
+    match args.command {
+        Some(Command::Embed { ref text, ref file, raw }) => {
+            embed(embedder?.as_ref(), text.as_deref(), file.as_deref(), raw).await
+        }
+        Some(Command::Doctor { expected_dim }) => {
+            doctor(args.provider, &config, embedder.as_deref(), expected_dim).await
+        }
+        None => index(&args, embedder?.as_ref(), &config).await,
+    }
+}
+
+/// Collects everything the indexing run writes out across files.
+struct Output {
+    jsonl: Option<Box<dyn Write>>,
+    dedup: Option<Deduplicator>,
+}
+
+async fn index(args: &Args, embedder: &dyn Embedder, config: &EmbeddingConfig) -> Result<()> {
+    let path = args.path.as_deref().context("Missing input path")?;
+    let options = ExtractOptions {
+        large_resource_bytes: args.large_resource_bytes,
+        chunking: args.max_chars.map(|max_chars| ChunkOptions {
+            max_chars,
+            overlap: args.overlap,
+            min_chars: args.min_chars,
+            merge_short: args.merge_short,
+        }),
+        boilerplate: args.strip_boilerplate.then(BoilerplateFilter::default),
+        normalize: args.normalize_whitespace,
+    };
+    let jsonl: Option<Box<dyn Write>> = match args.jsonl_out.as_deref() {
+        None => None,
+        Some("-") => Some(Box::new(io::stdout().lock())),
+        Some(path) => Some(Box::new(BufWriter::new(
+            File::create(path).with_context(|| format!("Failed to create {}", path))?,
+        ))),
+    };
+    let mut output = Output {
+        jsonl,
+        dedup: args.dedup_threshold.map(Deduplicator::new),
+    };
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    tokio::spawn({
+        let interrupted = interrupted.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                interrupted.store(true, Ordering::SeqCst);
+                eprintln!("\nInterrupted, finishing the requests in flight. Press Ctrl-C again to quit immediately.");
+            }
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    });
+
+    // Extract everything before embedding, so an interrupted run can say how much is left.
+    let mut extracted = Vec::new();
+    if Path::new(path).is_dir() {
+        let files = supported_files(Path::new(path), args.glob.as_deref())?;
+        for file in files {
+            let name = file.strip_prefix(path).unwrap_or(&file).to_string_lossy().into_owned();
+            let file = file.to_string_lossy().into_owned();
+            // One malformed file should not stop the rest of the directory from being indexed.
+            match extract(args, &options, &file) {
+                Ok(chunks) => {
+                    eprintln!("{}: {} chunk(s)", file, chunks.len());
+                    extracted.push((file, name, chunks));
+                }
+                Err(e) => eprintln!("Skipping {}: {:#}", file, e),
+            }
+        }
+    } else {
+        let chunks = extract(args, &options, path)?;
+        let name = Path::new(path)
+            .file_name()
+            .map_or_else(|| path.to_string(), |name| name.to_string_lossy().into_owned());
+        extracted.push((path.to_string(), name, chunks));
+    }
+
+    let total: usize = extracted
+        .iter()
+        .flat_map(|(_, _, chunks)| chunks)
+        .filter(|(chunk, _)| !chunk.trim().is_empty())
+        .count();
+    let mut written = 0;
+    for (file, name, chunks) in extracted {
+        if interrupted.load(Ordering::SeqCst) {
+            break;
+        }
+        written +=
+            embed_file(args, embedder, config, &Source { path: &file, name }, chunks, &mut output, &interrupted).await?;
+    }
+
+    let removed = output.dedup.as_ref().map(Deduplicator::removed);
+    if let Some(removed) = removed {
+        eprintln!("Removed {} duplicate chunk(s)", removed);
+    }
+    if interrupted.load(Ordering::SeqCst) {
+        let duplicates = removed.map_or_else(String::new, |removed| format!(", {} dropped as duplicates", removed));
+        eprintln!("Stopped early: saved {} of {} chunks{}", written, total, duplicates);
+        // Exit like an interrupted process so scripts don't take partial output as complete.
+        // `exit` skips destructors, so close the output first.
+        drop(output.jsonl);
+        std::process::exit(130);
+    }
+    Ok(())
+}
+
+fn extract(args: &Args, options: &ExtractOptions, path: &str) -> Result<Vec<(String, ChunkMetadata)>> {
+    let with_figures = args.include_figures && is_epub(path);
+    let mut chunks = match file_chunks(path, &args.html_backend, options) {
+        Ok(chunks) => chunks,
+        // An image-only book can still be indexed by its figures.
+        Err(CipherError::NoText(_)) if with_figures => Vec::new(),
+        Err(e) => return Err(e).context("Failed to extract chunks"),
+    };
+    if with_figures {
+        let figures = epub_figures(path).context("Failed to extract figures")?;
+        // Only the caption is embedded; the image path would just add noise to the vector.
+        chunks.extend(figures.iter().map(|figure| (figure.caption.clone(), figure.metadata())));
+    }
+    if chunks.is_empty() {
+        return Err(CipherError::NoText(path.into()).into());
+    }
+    Ok(chunks)
+}
+
+fn is_epub(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("epub"))
+}
+
+/// A file being indexed. `name` identifies it in record ids: its path relative to the
+/// indexed directory, or its file name when indexed on its own.
+struct Source<'a> {
+    path: &'a str,
+    name: String,
+}
+
+/// Embeds the chunks of one file and returns how many were saved.
+async fn embed_file(
+    args: &Args,
+    embedder: &dyn Embedder,
+    config: &EmbeddingConfig,
+    source: &Source<'_>,
+    mut chunks: Vec<(String, ChunkMetadata)>,
+    output: &mut Output,
+    interrupted: &AtomicBool,
+) -> Result<usize> {
+    let Output { jsonl: jsonl_out, dedup } = output;
+    let path = source.path;
+    // Duplicates are blanked rather than removed: the stream skips empty chunks but keeps
+    // the positions of the rest, so chunk indices and ids still match the source.
+    if let Some(dedup) = dedup.as_mut() {
+        for (chunk, _) in chunks.iter_mut() {
+            if !dedup.is_new_text(chunk) {
+                chunk.clear();
+            }
+        }
+    }
+    let (markdown_chunks, chunk_metadata): (Vec<_>, Vec<_>) = chunks.into_iter().unzip();
+    let prefixes = if args.contextual_prefix {
+        let title = if is_epub(path) {
+            epub_title(path).context("Failed to read EPUB metadata")?
+        } else {
+            None
+        };
+        let file_name = Path::new(path).file_name().map(|name| name.to_string_lossy());
+        let title = title.as_deref().or(file_name.as_deref()).unwrap_or(path);
+        // The TOC entry names the chapter; the nearest heading stands in when there is none.
+        let prefixes: Vec<String> = chunk_metadata
+            .iter()
+            .map(|metadata| {
+                let chapter = metadata.get("toc_title").or_else(|| metadata.get("heading"));
+                contextual_prefix(title, chapter.map(String::as_str))
+            })
+            .collect();
+        Some(prefixes)
+    } else {
+        None
+    };
+
+    let mut written = 0;
+    stream_embeddings_with_embedder(
+        embedder,
+        config,
+        markdown_chunks,
+        prefixes.as_deref(),
+        |progress| {
+            eprint!("\rEmbedding {} {}", progress.bar(30), progress);
+            if progress.done == progress.total {
+                eprintln!();
+            }
+            if interrupted.load(Ordering::SeqCst) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        },
+        |chunk_index, content, embedding| {
+            if let Some(dedup) = dedup.as_mut() {
+                if !dedup.is_new_embedding(&embedding)? {
+                    return Ok(());
+                }
+            }
+            written += 1;
+            let Some(out) = jsonl_out.as_mut() else {
+                println!("Embedding for chunk: {:?}", embedding);
+                return Ok(());
+            };
+            let mut metadata = chunk_metadata[chunk_index].clone();
+            metadata.insert("source".to_string(), path.to_string());
+            metadata.insert("chunk_index".to_string(), chunk_index.to_string());
+            metadata.insert("embedding_model".to_string(), embedder.model().to_string());
+            metadata.insert("embedding_normalized".to_string(), config.normalize.to_string());
+            let id = match args.id_scheme {
+                IdScheme::Position => format!("{}#{}", source.name, chunk_index),
+                IdScheme::Content => content_chunk_id(&source.name, chunk_index, &content),
+            };
+            let record = json!({
+                "id": id,
+                "content": content,
+                "embedding": embedding,
+                "metadata": metadata,
+            });
+            writeln!(out, "{}", record)?;
+            out.flush()?;
+            Ok(())
+        },
+    )
+    .await?;
+    Ok(written)
+}
+
+async fn embed(embedder: &dyn Embedder, text: Option<&str>, file: Option<&str>, raw: bool) -> Result<()> {
+    let text = match (text, file) {
+        (Some(text), _) => text.to_string(),
+        (None, Some(file)) => std::fs::read_to_string(file).with_context(|| format!("Failed to read {}", file))?,
+        (None, None) => anyhow::bail!("Either --text or --file is required"),
+    };
+    let embedding = embedder.embed(&text).await?;
+    if raw {
+        println!("{:?}", embedding);
+    } else {
+        let norm = embedding.iter().map(|x| x * x).sum::<f64>().sqrt();
+        println!("Dimension: {}", embedding.len());
+        println!("Norm: {:.6}", norm);
+    }
+    Ok(())
+}
+
+/// Runs the Ollama checks only for the Ollama provider; any provider gets a test embedding.
+async fn doctor(
+    provider: Provider,
+    config: &EmbeddingConfig,
+    embedder: std::result::Result<&dyn Embedder, &CipherError>,
+    expected_dim: Option<usize>,
+) -> Result<()> {
+    let mut failures = 0;
+    let mut report = |ok: bool, check: &str, detail: String| {
+        println!("[{}] {}: {}", if ok { "PASS" } else { "FAIL" }, check, detail);
+        if !ok {
+            failures += 1;
+        }
+    };
+
+    let models = match provider {
+        Provider::Ollama => Some(list_local_models(config).await),
+        Provider::Openai => None,
+    };
+    match models {
+        None => {}
+        Some(Ok(models)) => {
+            report(true, "Ollama reachable", format!("{} model(s) installed", models.len()));
+            let model = &config.model;
+            let installed = models
+                .iter()
+                .any(|name| name == model || name.starts_with(&format!("{}:", model)));
+            let detail = if installed {
+                model.clone()
+            } else {
+                format!("{} not found, run `ollama pull {}`", model, model)
+            };
+            report(installed, "Embedding model installed", detail);
+        }
+        Some(Err(e)) => report(false, "Ollama reachable", format!("{:#}, is `ollama serve` running?", e)),
+    }
+
+    let embedding = match embedder {
+        Ok(embedder) => embedder.embed("cipher health check").await.map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match embedding {
+        Ok(embedding) => {
+            let ok = !embedding.is_empty() && expected_dim.is_none_or(|dim| dim == embedding.len());
+            let detail = match expected_dim {
+                Some(dim) if dim != embedding.len() => format!("got dimension {}, expected {}", embedding.len(), dim),
+                _ => format!("dimension {}", embedding.len()),
+            };
+            report(ok, "Embedding generation", detail);
+        }
+        Err(e) => report(false, "Embedding generation", e),
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} check(s) failed", failures);
     }
     Ok(())
 }
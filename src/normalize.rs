@@ -0,0 +1,61 @@
+const ENTITIES: &[(&str, &str)] = &[
+    ("&nbsp;", " "),
+    ("&#160;", " "),
+    ("&lt;", "<"),
+    ("&gt;", ">"),
+    ("&quot;", "\""),
+    ("&#39;", "'"),
+    ("&apos;", "'"),
+    ("&mdash;", "\u{2014}"),
+    ("&ndash;", "\u{2013}"),
+    ("&hellip;", "\u{2026}"),
+];
+
+/// Tidies converter output before chunking: decodes common HTML entities, collapses runs
+/// of spaces within a line and runs of blank lines, and trims the ends. Leading
+/// indentation is kept since it is meaningful in markdown. Normalizing twice gives the
+/// same text as normalizing once.
+pub fn normalize_markdown(markdown: &str) -> String {
+    let decoded = decode_entities(markdown);
+    let mut lines = Vec::new();
+    for line in decoded.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            if lines.last().is_some_and(|last: &String| !last.is_empty()) {
+                lines.push(String::new());
+            }
+            continue;
+        }
+        let body = line.trim_start();
+        let indent = &line[..line.len() - body.len()];
+        lines.push(format!("{}{}", indent, body.split_whitespace().collect::<Vec<_>>().join(" ")));
+    }
+    lines.join("\n").trim().to_string()
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find(['&', '\u{a0}']) {
+        decoded.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        if let Some(after) = rest.strip_prefix('\u{a0}') {
+            decoded.push(' ');
+            rest = after;
+        } else if let Some((entity, replacement)) = ENTITIES.iter().find(|(entity, _)| rest.starts_with(entity)) {
+            decoded.push_str(replacement);
+            rest = &rest[entity.len()..];
+        } else if let Some(after) = rest.strip_prefix("&amp;") {
+            // `&amp;lt;` is an escaped entity; decoding it would let a second pass turn it
+            // into `<`, so it is left alone.
+            let escapes_entity = after.starts_with("amp;") || ENTITIES.iter().any(|(entity, _)| after.starts_with(&entity[1..]));
+            decoded.push_str(if escapes_entity { "&amp;" } else { "&" });
+            rest = after;
+        } else {
+            decoded.push('&');
+            rest = &rest[1..];
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
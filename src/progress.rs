@@ -0,0 +1,89 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+const DEFAULT_WINDOW: usize = 20;
+
+/// A snapshot of how far the embedding phase has got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    pub fn percent(&self) -> usize {
+        if self.total == 0 {
+            return 100;
+        }
+        self.done * 100 / self.total
+    }
+
+    /// A text progress bar `width` cells wide, e.g. `[=====>    ]`.
+    pub fn bar(&self, width: usize) -> String {
+        let filled = (self.done * width).checked_div(self.total).map_or(width, |filled| filled.min(width));
+        let head = if filled < width && filled > 0 { ">" } else { "" };
+        let filled = filled - head.len();
+        format!("[{}{}{}]", "=".repeat(filled), head, " ".repeat(width - filled - head.len()))
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} ({}%)", self.done, self.total, self.percent())?;
+        if let Some(eta) = self.eta {
+            write!(f, " ~{} remaining", format_duration(eta))?;
+        }
+        Ok(())
+    }
+}
+
+/// Estimates the remaining time from a rolling average of recent per-chunk latencies.
+#[derive(Debug, Clone)]
+pub struct EtaEstimator {
+    window: VecDeque<Duration>,
+    capacity: usize,
+}
+
+impl Default for EtaEstimator {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl EtaEstimator {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            window: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        if self.window.len() == self.capacity {
+            self.window.pop_front();
+        }
+        self.window.push_back(latency);
+    }
+
+    pub fn estimate(&self, remaining: usize) -> Option<Duration> {
+        if self.window.is_empty() {
+            return None;
+        }
+        let average = self.window.iter().sum::<Duration>() / self.window.len() as u32;
+        Some(average * remaining as u32)
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
+    } else if secs >= 60 {
+        format!("{}m", secs.div_ceil(60))
+    } else {
+        format!("{}s", secs)
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+
+/// How often to retry a request that failed for a transient reason, such as a dropped
+/// connection or an overloaded server. The wait doubles after every failed attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first one.
+    pub max_attempts: usize,
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    pub async fn run<T, E, F, Fut>(&self, mut request: F) -> Result<T, E>
+    where
+        E: Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Err(e) if attempt < self.max_attempts && is_transient(&e) => {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// ollama-rs only keeps the error message, so transient failures are recognised by the
+/// wording reqwest and the embedding servers use for them. Anything else, like an unknown
+/// model or a malformed request, fails straight away.
+fn is_transient(error: &impl Display) -> bool {
+    let message = error.to_string().to_lowercase();
+    [
+        "error sending request",
+        "connection",
+        "timed out",
+        "timeout",
+        "busy",
+        "overloaded",
+        "unavailable",
+        "try again",
+        "too many requests",
+        "rate limit",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
@@ -0,0 +1,41 @@
+use crate::{CipherError, Result};
+
+/// Cosine of the angle between `a` and `b`, from -1.0 to 1.0. Returns 0.0 when either
+/// vector is all zeros, and [`CipherError::DimensionMismatch`] when the lengths differ.
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> Result<f64> {
+    let dot = dot_product(a, b)?;
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Ok(0.0);
+    }
+    Ok(dot / (norm_a * norm_b))
+}
+
+/// Sum of the element-wise products. Fails when the lengths differ.
+pub fn dot_product(a: &[f64], b: &[f64]) -> Result<f64> {
+    check_dimensions(a.len(), b.len())?;
+    Ok(a.iter().zip(b).map(|(x, y)| x * y).sum())
+}
+
+/// Straight-line distance between `a` and `b`. Fails when the lengths differ.
+pub fn euclidean_distance(a: &[f64], b: &[f64]) -> Result<f64> {
+    check_dimensions(a.len(), b.len())?;
+    Ok(a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f64>().sqrt())
+}
+
+/// Scales `v` to unit length, so dot products of normalized vectors equal their cosine
+/// similarity. An all-zero vector is left as is.
+pub fn l2_normalize(v: &mut [f64]) {
+    let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+pub(crate) fn check_dimensions(expected: usize, actual: usize) -> Result<()> {
+    if expected != actual {
+        return Err(CipherError::DimensionMismatch { expected, actual });
+    }
+    Ok(())
+}
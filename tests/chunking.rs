@@ -0,0 +1,234 @@
+use anyhow::Result;
+use cipher::{
+    chunk_markdown, chunk_markdown_with_headings, epub_chunks, epub_to_markdown_with, file_chunks, BoilerplateFilter, CipherError,
+    ChunkOptions, ExtractOptions, Html2Md, HtmlBackend, HtmlToMarkdown,
+};
+
+const TEXT: &str = "The quick brown fox jumps over the lazy dog.\n\n\
+    A second paragraph that is quite a bit longer than the first one and must be split between words.\n\n\
+    Short end.";
+
+#[test]
+fn test_chunks_respect_max_chars_and_word_boundaries() {
+    let options = ChunkOptions {
+        max_chars: 40,
+        overlap: 0,
+        ..ChunkOptions::default()
+    };
+    let chunks = chunk_markdown(TEXT, &options);
+    assert!(chunks.len() > 3);
+    let words: Vec<&str> = TEXT.split_whitespace().collect();
+    for chunk in &chunks {
+        assert!(chunk.chars().count() <= 40, "chunk too long: {:?}", chunk);
+        for word in chunk.split_whitespace() {
+            assert!(words.contains(&word), "word was cut: {:?}", word);
+        }
+    }
+    assert_eq!(chunks.join(" ").split_whitespace().collect::<Vec<_>>(), words);
+}
+
+#[test]
+fn test_chunks_keep_fitting_paragraphs_together() {
+    let options = ChunkOptions {
+        max_chars: 1000,
+        overlap: 0,
+        ..ChunkOptions::default()
+    };
+    let chunks = chunk_markdown(TEXT, &options);
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].matches("\n\n").count(), 2);
+}
+
+#[test]
+fn test_chunks_overlap_on_word_boundaries() {
+    let options = ChunkOptions {
+        max_chars: 50,
+        overlap: 15,
+        ..ChunkOptions::default()
+    };
+    let chunks = chunk_markdown(TEXT, &options);
+    for pair in chunks.windows(2) {
+        assert!(pair[0].chars().count() <= 50);
+        let first_word = pair[1].split_whitespace().next().unwrap();
+        let prev_words: Vec<&str> = pair[0].split_whitespace().collect();
+        assert!(prev_words.contains(&first_word), "{:?} does not overlap {:?}", pair[1], pair[0]);
+    }
+}
+
+#[test]
+fn test_epub_to_markdown_with_chunking() -> Result<()> {
+    let options = ExtractOptions {
+        chunking: Some(ChunkOptions {
+            max_chars: 500,
+            overlap: 50,
+            ..ChunkOptions::default()
+        }),
+        ..ExtractOptions::default()
+    };
+    let chunks = epub_to_markdown_with("testdata/pg35542.epub", &HtmlBackend::Html2Md, &options)?;
+    assert!(chunks.len() > 10);
+    assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 500));
+    Ok(())
+}
+
+#[test]
+fn test_min_chars_counts_characters_not_bytes() {
+    // 9 characters but 27 bytes of UTF-8.
+    let japanese = "日本語の文章です。";
+    // 40 ASCII characters.
+    let english = "A short sentence of exactly forty chars.";
+    let text = format!("{}\n\n{}", japanese, english);
+    let options = ChunkOptions {
+        max_chars: 40,
+        overlap: 0,
+        min_chars: 10,
+        merge_short: false,
+    };
+
+    let chunks = chunk_markdown(&text, &options);
+    assert_eq!(chunks, vec![english.to_string()]);
+
+    let chunks = chunk_markdown(&text, &ChunkOptions { min_chars: 9, ..options });
+    assert_eq!(chunks, vec![japanese.to_string(), english.to_string()]);
+}
+
+#[test]
+fn test_short_chunks_are_merged_instead_of_dropped() {
+    let text = "\"Hello.\"\n\n\"Hi.\"\n\n\"Well?\"\n\n\"Fine.\"";
+    let options = ChunkOptions {
+        max_chars: 12,
+        overlap: 0,
+        min_chars: 20,
+        merge_short: false,
+    };
+    assert!(chunk_markdown(text, &options).is_empty());
+
+    let chunks = chunk_markdown(text, &ChunkOptions { merge_short: true, ..options });
+    assert_eq!(chunks, vec![text.to_string()]);
+}
+
+#[test]
+fn test_merged_chunks_do_not_repeat_the_overlap() {
+    let text = "one two three four five six seven eight nine ten\n\neleven twelve";
+    let options = ChunkOptions {
+        max_chars: 60,
+        overlap: 10,
+        min_chars: 30,
+        merge_short: true,
+    };
+    let chunks = chunk_markdown(text, &options);
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].matches("ten").count(), 1);
+    assert!(chunks[0].ends_with("eleven twelve"));
+}
+
+#[test]
+fn test_markdown_files_are_chunked_without_conversion() -> Result<()> {
+    let options = ExtractOptions {
+        chunking: Some(ChunkOptions {
+            max_chars: 100,
+            overlap: 0,
+            min_chars: 0,
+            merge_short: false,
+        }),
+        ..ExtractOptions::default()
+    };
+    let chunks = file_chunks("testdata/notes.md", &Html2Md, &options)?;
+    assert_eq!(chunks.len(), 2);
+    assert!(chunks[0].0.starts_with("# Rodent notes"));
+    assert!(chunks[1].0.starts_with("House mice"));
+    // Chunks of a markdown file carry their headings, like those of an EPUB.
+    for (_, metadata) in &chunks {
+        assert_eq!(metadata.get("heading").map(String::as_str), Some("Rodent notes"));
+        assert_eq!(metadata.get("section").map(String::as_str), Some("Rodent notes"));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_boilerplate_is_stripped_from_text_files() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("cipher-boilerplate-{}.txt", std::process::id()));
+    std::fs::write(
+        &path,
+        "The Project Gutenberg eBook of Rats\n\n*** START OF THE PROJECT GUTENBERG EBOOK RATS ***\n\n\
+         The brown rat is larger than the black rat.\n\n\
+         *** END OF THE PROJECT GUTENBERG EBOOK RATS ***\n\nSection 1. General Terms of Use\n",
+    )?;
+    let options = ExtractOptions {
+        boilerplate: Some(BoilerplateFilter::default()),
+        ..ExtractOptions::default()
+    };
+    let chunks = file_chunks(path.to_str().unwrap(), &Html2Md, &options)?;
+    let text: Vec<&str> = chunks.iter().map(|(chunk, _)| chunk.as_str()).collect();
+    assert_eq!(text, vec!["The brown rat is larger than the black rat."]);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_unsupported_file_types_are_rejected() {
+    let err = file_chunks("Cargo.toml", &Html2Md, &ExtractOptions::default()).unwrap_err();
+    assert!(matches!(err, CipherError::UnsupportedFile(_)));
+    assert!(err.to_string().contains("Unsupported file type"));
+}
+
+#[test]
+fn test_missing_files_report_the_path() {
+    let err = file_chunks("testdata/missing.md", &Html2Md, &ExtractOptions::default()).unwrap_err();
+    assert!(matches!(&err, CipherError::File { path, .. } if path.ends_with("missing.md")));
+    let err = file_chunks("testdata/missing.epub", &Html2Md, &ExtractOptions::default()).unwrap_err();
+    assert!(matches!(err, CipherError::Epub { .. }));
+}
+
+#[test]
+fn test_files_without_text_are_rejected() {
+    let err = file_chunks("testdata/pictures-only.epub", &Html2Md, &ExtractOptions::default()).unwrap_err();
+    assert!(matches!(&err, CipherError::NoText(path) if path.ends_with("pictures-only.epub")));
+    assert!(err.to_string().contains("No extractable text found in testdata/pictures-only.epub"));
+}
+
+#[test]
+fn test_chunks_record_their_heading_path() {
+    let html = "<h1>House Rats</h1><p>Rats live near people.</p>\
+        <h2>Habits</h2><p>They feed at night and hide by day.</p>\
+        <h3><a id=\"nests\"></a><a href=\"#nests\">Nests</a></h3><p>Nests are built from rags and paper.</p>\
+        <h2>Control</h2><p>Traps work better than poison indoors.</p>";
+    let markdown = Html2Md.convert(html);
+    let options = ChunkOptions {
+        max_chars: 60,
+        overlap: 0,
+        min_chars: 0,
+        merge_short: false,
+    };
+    let chunks = chunk_markdown_with_headings(&markdown, &options);
+    let headings: Vec<(String, Vec<&str>)> = chunks
+        .iter()
+        .map(|chunk| (chunk.text.clone(), chunk.headings.iter().map(String::as_str).collect()))
+        .collect();
+    let find = |needle: &str| {
+        headings
+            .iter()
+            .find(|(text, _)| text.contains(needle))
+            .map(|(_, path)| path.clone())
+            .unwrap()
+    };
+    assert_eq!(find("Rats live near people."), vec!["House Rats"]);
+    assert_eq!(find("They feed at night"), vec!["House Rats", "Habits"]);
+    assert_eq!(find("Nests are built"), vec!["House Rats", "Habits", "Nests"]);
+    assert_eq!(find("Traps work better"), vec!["House Rats", "Control"]);
+}
+
+#[test]
+fn test_epub_chunks_carry_heading_metadata() -> Result<()> {
+    let options = ExtractOptions {
+        chunking: Some(ChunkOptions::default()),
+        ..ExtractOptions::default()
+    };
+    let chunks = epub_chunks("testdata/pg35542.epub", &Html2Md, &options)?;
+    let (_, metadata) = chunks
+        .iter()
+        .find(|(_, metadata)| metadata.get("heading").is_some_and(|heading| heading == "POISONS."))
+        .unwrap();
+    assert!(metadata["section"].ends_with(" > POISONS."), "{}", metadata["section"]);
+    Ok(())
+}
@@ -1,6 +1,16 @@
+mod common;
+
 use assert_cmd::prelude::*;
+use common::{FakeOllama, Reply};
 use predicates::prelude::*;
+use std::io::{BufRead, BufReader};
 use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+async fn fake_ollama() -> FakeOllama {
+    FakeOllama::start(|_| (Duration::ZERO, Reply::Embedding(vec![0.1, 0.2, 0.3]))).await
+}
 
 #[test]
 fn test_cli_help() {
@@ -8,13 +18,369 @@ fn test_cli_help() {
     cmd.arg("--help");
     cmd.assert()
         .success()
-        .stdout(predicate::str::contains("Usage"));
+        .stdout(predicate::str::contains("Usage"))
+        .stdout(predicate::str::contains("Port of the Ollama server"));
+}
+
+#[test]
+fn test_cli_embed_help_describes_its_inputs() {
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["embed", "--help"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Text to embed"))
+        .stdout(predicate::str::contains("Embed the whole contents of this file"));
 }
 
 #[test]
 fn test_cli_epub_to_markdown() {
     let mut cmd = Command::cargo_bin("cipher").unwrap();
-    cmd.arg("testdata/pg35542.epub");
+    cmd.args(["testdata/pg35542.epub", "--no-cache"]);
+    cmd.assert()
+        .success();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_indexes_markdown_files() {
+    let ollama = fake_ollama().await;
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.arg("testdata/notes.md")
+        .args(["--no-cache", "--ollama-port", &ollama.port.to_string(), "--jsonl-out", "-"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+
+    let records: Vec<serde_json::Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(!records.is_empty());
+    assert!(records[0]["content"].as_str().unwrap().starts_with("# Rodent notes"));
+    for record in &records {
+        assert_eq!(record["metadata"]["source"], "testdata/notes.md");
+        assert!(record["id"].as_str().unwrap().starts_with("notes.md#"));
+    }
+}
+
+#[test]
+fn test_cli_rejects_unknown_html_backends() {
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["testdata/pg35542.epub", "--html-backend", "pandoc"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value 'pandoc'"))
+        .stderr(predicate::str::contains("[possible values: html2md, prose]"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_figures_embed_their_captions() {
+    let ollama = fake_ollama().await;
+    let out = std::env::temp_dir().join(format!("cipher-figures-{}.jsonl", std::process::id()));
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.arg("testdata/pg35542-images.epub")
+        .args(["--no-cache", "--include-figures", "--ollama-port", &ollama.port.to_string(), "--jsonl-out"])
+        .arg(&out);
+    cmd.assert()
+        .success();
+
+    let contents = std::fs::read_to_string(&out).unwrap();
+    let rat = contents
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .find(|record| record["metadata"]["type"] == "figure" && record["content"] == "Fig. 1.—Brown rat.")
+        .expect("figure record");
+    assert!(rat["metadata"]["resource"].as_str().unwrap().ends_with("fig-01-400.png"));
+    std::fs::remove_file(&out).unwrap();
+}
+
+#[test]
+fn test_cli_rejects_unsupported_files() {
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["Cargo.toml", "--no-cache"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Unsupported file type"));
+}
+
+#[test]
+fn test_cli_rejects_files_without_text() {
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["testdata/pictures-only.epub", "--no-cache"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("No extractable text found"));
+}
+
+#[test]
+fn test_cli_indexes_directories_and_skips_broken_files() {
+    let dir = std::env::temp_dir().join(format!("cipher-index-dir-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("nested")).unwrap();
+    std::fs::write(dir.join("broken.epub"), "not a zip").unwrap();
+    std::fs::write(dir.join("nested/notes.md"), "Some notes about rats.").unwrap();
+    std::fs::write(dir.join("ignored.pdf"), "%PDF").unwrap();
+
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.arg(&dir).arg("--no-cache");
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Skipping"))
+        .stderr(predicate::str::contains("notes.md: 1 chunk(s)"))
+        .stderr(predicate::str::contains("ignored.pdf").not());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_ids_use_the_path_within_the_directory() {
+    let ollama = fake_ollama().await;
+    let dir = std::env::temp_dir().join(format!("cipher-same-names-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("a")).unwrap();
+    std::fs::create_dir_all(dir.join("b")).unwrap();
+    std::fs::write(dir.join("a/notes.md"), "Notes about rats.").unwrap();
+    std::fs::write(dir.join("b/notes.md"), "Notes about mice.").unwrap();
+    let out = dir.join("out.jsonl");
+
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.arg(&dir)
+        .args(["--no-cache", "--ollama-port", &ollama.port.to_string(), "--jsonl-out"])
+        .arg(&out);
+    cmd.assert()
+        .success();
+
+    let contents = std::fs::read_to_string(&out).unwrap();
+    let ids: Vec<String> = contents
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["id"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(ids, vec!["a/notes.md#0", "b/notes.md#0"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_dedup_keeps_source_positions() {
+    // Each distinct text gets its own axis so only exact repeats count as duplicates.
+    let ollama = FakeOllama::start(|prompt| {
+        let axis = ["Alpha", "Bravo", "Charlie"].iter().position(|word| prompt.contains(word)).unwrap_or(3);
+        let mut embedding = vec![0.0; 4];
+        embedding[axis] = 1.0;
+        (Duration::ZERO, Reply::Embedding(embedding))
+    })
+    .await;
+    let dir = std::env::temp_dir().join(format!("cipher-dedup-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("dup.md");
+    std::fs::write(&file, "Alpha paragraph.\n\nAlpha paragraph.\n\nBravo paragraph.\n").unwrap();
+    let out = dir.join("out.jsonl");
+
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.arg(&file)
+        .args(["--no-cache", "--ollama-port", &ollama.port.to_string(), "--max-chars", "20", "--overlap", "0", "--min-chars", "0"])
+        .args(["--dedup-threshold", "0.99", "--jsonl-out"])
+        .arg(&out);
+    cmd.assert()
+        .success();
+
+    let contents = std::fs::read_to_string(&out).unwrap();
+    let ids: Vec<String> = contents
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["id"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(ids, vec!["dup.md#0", "dup.md#2"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_openai_provider_requires_api_key() {
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.env_remove("OPENAI_API_KEY");
+    cmd.args(["--no-cache", "embed", "--text", "hello", "--provider", "openai"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("OPENAI_API_KEY"));
+}
+
+#[test]
+fn test_cli_doctor_reports_a_missing_openai_key() {
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.env_remove("OPENAI_API_KEY");
+    cmd.args(["--provider", "openai", "doctor"]);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("[FAIL] Embedding generation: Configuration error: OPENAI_API_KEY is not set"))
+        .stdout(predicate::str::contains("Ollama").not());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_doctor_checks_the_openai_provider() {
+    let server = fake_ollama().await;
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.env("OPENAI_API_KEY", "test-key")
+        .env("OPENAI_BASE_URL", format!("http://127.0.0.1:{}/v1", server.port))
+        .args(["--provider", "openai", "doctor"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("[PASS] Embedding generation: dimension 3"))
+        .stdout(predicate::str::contains("Ollama").not());
+    assert_eq!(server.requested_models(), vec!["text-embedding-3-small"]);
+}
+
+#[test]
+fn test_cli_embed_requires_input() {
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["--no-cache", "embed"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--text"));
+}
+
+#[test]
+fn test_cli_requires_epub_path_without_subcommand() {
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.assert()
+        .failure();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_doctor_reports_checks() {
+    let ollama = fake_ollama().await;
+    ollama.install("mxbai-embed-large:latest");
+    let port = ollama.port.to_string();
+
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["doctor", "--ollama-port", &port]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("[PASS] Ollama reachable: 1 model(s) installed"))
+        .stdout(predicate::str::contains("[PASS] Embedding model installed"))
+        .stdout(predicate::str::contains("[PASS] Embedding generation: dimension 3"));
+
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["doctor", "--ollama-port", &port, "--expected-dim", "1024"]);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("[FAIL] Embedding generation: got dimension 3, expected 1024"));
+
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["doctor", "--ollama-port", &port, "--embedding-model", "missing-model"]);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("[FAIL] Embedding model installed: missing-model not found"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_jsonl_out_writes_json_lines() {
+    let ollama = fake_ollama().await;
+    let out = std::env::temp_dir().join(format!("cipher-jsonl-{}.jsonl", std::process::id()));
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.arg("testdata/pg35542.epub")
+        .args(["--no-cache", "--ollama-port", &ollama.port.to_string(), "--jsonl-out"])
+        .arg(&out);
     cmd.assert()
         .success();
+
+    let contents = std::fs::read_to_string(&out).unwrap();
+    let mut chunk_indices = Vec::new();
+    for line in contents.lines() {
+        let record: serde_json::Value = serde_json::from_str(line).unwrap();
+        let chunk_index: usize = record["metadata"]["chunk_index"].as_str().unwrap().parse().unwrap();
+        assert_eq!(record["id"], format!("pg35542.epub#{}", chunk_index));
+        assert_eq!(record["embedding"], serde_json::json!([0.1, 0.2, 0.3]));
+        assert_eq!(record["metadata"]["source"], "testdata/pg35542.epub");
+        chunk_indices.push(chunk_index);
+    }
+    assert!(chunk_indices.len() > 1);
+    assert!(chunk_indices.windows(2).all(|pair| pair[0] < pair[1]));
+    std::fs::remove_file(&out).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_embedding_model_flag_reaches_subcommands() {
+    let ollama = fake_ollama().await;
+    ollama.install("nomic-embed-text:latest");
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["--embedding-model", "nomic-embed-text", "--ollama-port", &ollama.port.to_string(), "doctor"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("[PASS] Embedding model installed: nomic-embed-text"));
+    let models = ollama.requested_models();
+    assert!(!models.is_empty());
+    assert!(models.iter().all(|model| model == "nomic-embed-text"));
+}
+
+// A worker thread is left free to serve the fake while the CLI runs.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_global_flags_work_before_subcommands() {
+    let ollama = fake_ollama().await;
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["--no-cache", "--ollama-port", &ollama.port.to_string(), "embed", "--text", "hi"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Dimension: 3"));
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_interrupt_saves_partial_output_and_exits_130() {
+    let ollama = FakeOllama::start(|_| (Duration::from_millis(100), Reply::Embedding(vec![0.1, 0.2, 0.3]))).await;
+    let dir = std::env::temp_dir().join(format!("cipher-interrupt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy("testdata/pg35542.epub", dir.join("a.epub")).unwrap();
+    std::fs::copy("testdata/pg35542.epub", dir.join("b.epub")).unwrap();
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("cipher"))
+        .arg(&dir)
+        .args(["--no-cache", "--ollama-port", &ollama.port.to_string(), "--max-chars", "200", "--jsonl-out", "-"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Interrupt once the first record is out, while the first file is still being embedded.
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut first_record = String::new();
+    stdout.read_line(&mut first_record).unwrap();
+    assert!(first_record.starts_with('{'));
+    let killed = Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+    assert!(killed.success());
+
+    let stderr = child.stderr.take().unwrap();
+    let records = 1 + stdout.lines().count();
+    let status = child.wait().unwrap();
+    let stderr = std::io::read_to_string(stderr).unwrap();
+    assert_eq!(status.code(), Some(130));
+    assert!(stderr.contains("finishing the requests in flight"));
+    // The total covers every file in the directory, not just the one that was started.
+    let per_file: usize = stderr
+        .lines()
+        .filter_map(|line| line.strip_suffix(" chunk(s)")?.rsplit(' ').next()?.parse::<usize>().ok())
+        .sum();
+    assert!(stderr.contains(&format!("Stopped early: saved {} of {} chunks", records, per_file)), "{}", stderr);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_contextual_prefix_names_the_book_and_chapter() {
+    let prompts = Arc::new(Mutex::new(Vec::new()));
+    let ollama = FakeOllama::start({
+        let prompts = prompts.clone();
+        move |prompt| {
+            prompts.lock().unwrap().push(prompt.to_string());
+            (Duration::ZERO, Reply::Embedding(vec![0.1, 0.2, 0.3]))
+        }
+    })
+    .await;
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.arg("testdata/pg35542.epub")
+        .args(["--no-cache", "--ollama-port", &ollama.port.to_string(), "--contextual-prefix", "--jsonl-out", "-"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+
+    let prompts = prompts.lock().unwrap();
+    assert!(prompts.iter().any(|prompt| prompt.starts_with("From 'House Rats and Mice', COMMUNITY EFFORTS: ")));
+    // Stored content stays unprefixed.
+    assert!(!String::from_utf8(output).unwrap().contains("From 'House Rats and Mice'"));
+}
+
+#[test]
+fn test_cli_ollama_host_and_port_are_used() {
+    let mut cmd = Command::cargo_bin("cipher").unwrap();
+    cmd.args(["doctor", "--ollama-host", "127.0.0.1", "--ollama-port", "9"]);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("http://127.0.0.1:9"));
 }
@@ -0,0 +1,195 @@
+#![allow(dead_code)]
+
+use cipher::Embedder;
+use futures_util::future::BoxFuture;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// What the fake Ollama should answer for one embedding prompt.
+pub enum Reply {
+    Embedding(Vec<f64>),
+    Error(u16, String),
+}
+
+pub type Handler = dyn Fn(&str) -> (Duration, Reply) + Send + Sync;
+
+/// A minimal stand-in for Ollama's `/api/embeddings` endpoint, listening on a random local port.
+/// Requests to `/v1/embeddings` get OpenAI-shaped answers instead, and `/api/tags` lists the
+/// models passed to [`FakeOllama::install`].
+pub struct FakeOllama {
+    pub port: u16,
+    pub requests: Arc<AtomicUsize>,
+    pub installed: Arc<Mutex<Vec<String>>>,
+    /// The `model` field of every embedding request, in arrival order.
+    pub models: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeOllama {
+    pub async fn start(handler: impl Fn(&str) -> (Duration, Reply) + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let installed = Arc::new(Mutex::new(Vec::new()));
+        let models = Arc::new(Mutex::new(Vec::new()));
+
+        let state = State {
+            handler,
+            installed: installed.clone(),
+            models: models.clone(),
+        };
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let state = state.clone();
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let _ = serve(socket, state).await;
+                });
+            }
+        });
+
+        Self {
+            port,
+            requests,
+            installed,
+            models,
+        }
+    }
+
+    /// Makes `model` show up in `/api/tags`.
+    pub fn install(&self, model: &str) {
+        self.installed.lock().unwrap().push(model.to_string());
+    }
+
+    pub fn requested_models(&self) -> Vec<String> {
+        self.models.lock().unwrap().clone()
+    }
+
+    pub fn request_count(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    handler: Arc<Handler>,
+    installed: Arc<Mutex<Vec<String>>>,
+    models: Arc<Mutex<Vec<String>>>,
+}
+
+async fn serve(mut socket: TcpStream, state: State) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let body_start = loop {
+        let mut chunk = [0u8; 4096];
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let headers = String::from_utf8_lossy(&buf[..body_start]).to_ascii_lowercase();
+    let content_length = headers
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .and_then(|len| len.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while buf.len() < body_start + content_length {
+        let mut chunk = [0u8; 4096];
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    if headers.starts_with("get /api/tags") {
+        let models: Vec<Value> = state
+            .installed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|name| json!({ "name": name, "modified_at": "", "size": 0 }))
+            .collect();
+        return respond(socket, 200, json!({ "models": models })).await;
+    }
+
+    let openai = headers.starts_with("post /v1/embeddings");
+    let request: Value = serde_json::from_slice(&buf[body_start..]).unwrap_or(Value::Null);
+    let prompt = request[if openai { "input" } else { "prompt" }]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    if let Some(model) = request["model"].as_str() {
+        state.models.lock().unwrap().push(model.to_string());
+    }
+    let (delay, reply) = (state.handler)(&prompt);
+    tokio::time::sleep(delay).await;
+
+    let (status, body) = match reply {
+        Reply::Embedding(embedding) if openai => (200, json!({ "data": [{ "embedding": embedding }] })),
+        Reply::Embedding(embedding) => (200, json!({ "embedding": embedding })),
+        Reply::Error(status, message) => (status, json!({ "error": message })),
+    };
+    respond(socket, status, body).await
+}
+
+async fn respond(mut socket: TcpStream, status: u16, body: Value) -> std::io::Result<()> {
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {} Fake\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// A deterministic, offline embedder: each word is hashed into one of `dim` buckets, so
+/// texts sharing words get similar vectors.
+pub struct HashEmbedder {
+    pub dim: usize,
+    pub calls: AtomicUsize,
+}
+
+impl HashEmbedder {
+    pub fn new(dim: usize) -> Self {
+        Self {
+            dim,
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+impl Embedder for HashEmbedder {
+    fn model(&self) -> &str {
+        "hash"
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, cipher::Result<Vec<f64>>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let mut embedding = vec![0.0; self.dim];
+        for word in text.split_whitespace() {
+            let hash = word
+                .to_lowercase()
+                .bytes()
+                .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3));
+            embedding[(hash % self.dim as u64) as usize] += 1.0;
+        }
+        Box::pin(async move { Ok(embedding) })
+    }
+}
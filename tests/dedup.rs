@@ -0,0 +1,31 @@
+use anyhow::Result;
+use cipher::{CipherError, Deduplicator};
+
+#[test]
+fn test_exact_duplicate_text_is_caught_by_hash() {
+    let mut dedup = Deduplicator::new(0.95);
+    assert!(dedup.is_new_text("End of Project Gutenberg"));
+    assert!(dedup.is_new_text("Chapter one"));
+    assert!(!dedup.is_new_text("  End of Project Gutenberg\n"));
+    assert_eq!(dedup.removed(), 1);
+}
+
+#[test]
+fn test_near_duplicate_embeddings_keep_first_occurrence() -> Result<()> {
+    let mut dedup = Deduplicator::new(0.95);
+    assert!(dedup.is_new_embedding(&[1.0, 0.0, 0.0])?);
+    assert!(dedup.is_new_embedding(&[0.0, 1.0, 0.0])?);
+    assert!(!dedup.is_new_embedding(&[0.99, 0.01, 0.0])?);
+    assert!(dedup.is_new_embedding(&[0.7, 0.7, 0.0])?);
+    assert_eq!(dedup.removed(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_mismatched_dimensions_are_reported() -> Result<()> {
+    let mut dedup = Deduplicator::new(0.95);
+    assert!(dedup.is_new_embedding(&[1.0, 0.0, 0.0])?);
+    let err = dedup.is_new_embedding(&[1.0, 0.0]).unwrap_err();
+    assert!(matches!(err, CipherError::DimensionMismatch { expected: 3, actual: 2 }));
+    Ok(())
+}
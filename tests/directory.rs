@@ -0,0 +1,50 @@
+use anyhow::Result;
+use cipher::supported_files;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn test_supported_files_lists_known_extensions() -> Result<()> {
+    let files = supported_files(Path::new("testdata"), None)?;
+    assert_eq!(
+        files,
+        vec![
+            PathBuf::from("testdata/notes.md"),
+            PathBuf::from("testdata/pg35542-images-3.epub"),
+            PathBuf::from("testdata/pg35542-images.epub"),
+            PathBuf::from("testdata/pg35542-nav-only.epub"),
+            PathBuf::from("testdata/pg35542.epub"),
+            PathBuf::from("testdata/pictures-only.epub"),
+            PathBuf::from("testdata/untitled-chapters.epub"),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_supported_files_applies_glob_to_file_names() -> Result<()> {
+    let files = supported_files(Path::new("testdata"), Some("pg*-images*.epub"))?;
+    assert_eq!(
+        files,
+        vec![
+            PathBuf::from("testdata/pg35542-images-3.epub"),
+            PathBuf::from("testdata/pg35542-images.epub"),
+        ]
+    );
+    assert_eq!(supported_files(Path::new("testdata"), Some("*.txt"))?, Vec::<PathBuf>::new());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_supported_files_does_not_follow_symlinked_directories() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("cipher-symlink-loop-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("a"))?;
+    std::fs::write(dir.join("a/notes.md"), "Some notes.")?;
+    std::os::unix::fs::symlink("..", dir.join("a/loop"))?;
+    std::os::unix::fs::symlink("notes.md", dir.join("a/linked.md"))?;
+
+    let files = supported_files(&dir, None)?;
+    assert_eq!(files, vec![dir.join("a/linked.md"), dir.join("a/notes.md")]);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,235 @@
+mod common;
+
+use anyhow::Result;
+use cipher::{
+    get_embeddings_with_embedder, get_embeddings_with_progress, get_single_embedding_with,
+    get_single_embedding_with_embedder, stream_embeddings, CipherError, Embedder, EmbeddingCache, EmbeddingConfig,
+    OpenAiEmbedder, RetryPolicy,
+};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use common::{FakeOllama, HashEmbedder, Reply};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_concurrent_embeddings_preserve_input_order() -> Result<()> {
+    // Longer prompts answer sooner, so responses complete in reverse order.
+    let ollama = FakeOllama::start(|prompt| {
+        let delay = Duration::from_millis(50 * (5 - prompt.len() as u64));
+        (delay, Reply::Embedding(vec![prompt.len() as f64]))
+    })
+    .await;
+    let config = EmbeddingConfig::default()
+        .with_host("127.0.0.1", ollama.port)
+        .with_concurrency(4);
+
+    let chunks = vec!["a", "bb", "ccc", "dddd"].into_iter().map(String::from).collect();
+    let embeddings = get_embeddings_with_progress(&config, chunks, |_| {}).await?;
+
+    assert_eq!(embeddings, vec![vec![1.0], vec![2.0], vec![3.0], vec![4.0]]);
+    assert_eq!(ollama.request_count(), 4);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cached_embeddings_skip_the_network() -> Result<()> {
+    let ollama = FakeOllama::start(|prompt| (Duration::ZERO, Reply::Embedding(vec![prompt.len() as f64, 1.0]))).await;
+    let cache_dir = std::env::temp_dir().join(format!("cipher-cache-test-{}", std::process::id()));
+    let config = EmbeddingConfig::default()
+        .with_host("127.0.0.1", ollama.port)
+        .with_cache(EmbeddingCache::new(&cache_dir));
+
+    let chunks = vec!["same text".to_string()];
+    let first = get_embeddings_with_progress(&config, chunks.clone(), |_| {}).await?;
+    let second = get_embeddings_with_progress(&config, chunks.clone(), |_| {}).await?;
+    assert_eq!(first, second);
+    assert_eq!(ollama.request_count(), 1);
+
+    // The cache is keyed by model as well as text.
+    let other_model = EmbeddingConfig { model: "other-model".to_string(), ..config };
+    get_embeddings_with_progress(&other_model, chunks.clone(), |_| {}).await?;
+    assert_eq!(ollama.request_count(), 2);
+
+    // And by server, since the same model name elsewhere may be a different model.
+    let other_server = FakeOllama::start(|prompt| (Duration::ZERO, Reply::Embedding(vec![prompt.len() as f64, 1.0]))).await;
+    let elsewhere = other_model.with_host("127.0.0.1", other_server.port);
+    get_embeddings_with_progress(&elsewhere, chunks, |_| {}).await?;
+    assert_eq!(other_server.request_count(), 1);
+
+    std::fs::remove_dir_all(&cache_dir)?;
+    Ok(())
+}
+
+fn quick_retry() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(10),
+    }
+}
+
+#[tokio::test]
+async fn test_transient_failures_are_retried() -> Result<()> {
+    let calls = AtomicUsize::new(0);
+    let ollama = FakeOllama::start(move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+        0 | 1 => (Duration::ZERO, Reply::Error(503, "server busy".to_string())),
+        _ => (Duration::ZERO, Reply::Embedding(vec![1.0, 2.0])),
+    })
+    .await;
+    let config = EmbeddingConfig::default()
+        .with_host("127.0.0.1", ollama.port)
+        .with_retry(quick_retry());
+
+    let embedding = get_single_embedding_with(&config, "hello").await?;
+    assert_eq!(embedding, vec![1.0, 2.0]);
+    assert_eq!(ollama.request_count(), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_permanent_failures_are_not_retried() -> Result<()> {
+    let ollama = FakeOllama::start(|_| (Duration::ZERO, Reply::Error(404, "model \"nope\" not found".to_string()))).await;
+    let config = EmbeddingConfig::new("nope")
+        .with_host("127.0.0.1", ollama.port)
+        .with_retry(quick_retry());
+
+    let err = get_single_embedding_with(&config, "hello").await.unwrap_err();
+    assert!(matches!(err, CipherError::Embedding(_)));
+    assert_eq!(ollama.request_count(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unreachable_ollama_is_not_an_embedding_failure() -> Result<()> {
+    // Bind and release a port so nothing is listening on it.
+    let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let config = EmbeddingConfig::default()
+        .with_host("127.0.0.1", port)
+        .with_retry(RetryPolicy::none());
+
+    let err = get_single_embedding_with(&config, "hello").await.unwrap_err();
+    assert!(matches!(&err, CipherError::Ollama { uri, .. } if uri.ends_with(&format!(":{}", port))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_openai_embedder_uses_the_embeddings_endpoint() -> Result<()> {
+    let server = FakeOllama::start(|input| (Duration::ZERO, Reply::Embedding(vec![input.len() as f64, 0.5]))).await;
+    let embedder = OpenAiEmbedder::new(format!("http://127.0.0.1:{}/v1/", server.port), "test-key", "text-embedding-3-small");
+
+    assert_eq!(embedder.model(), "text-embedding-3-small");
+    assert_eq!(embedder.embed("four").await?, vec![4.0, 0.5]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_openai_embedder_gives_up_on_a_slow_server() -> Result<()> {
+    let server = FakeOllama::start(|_| (Duration::from_secs(5), Reply::Embedding(vec![1.0]))).await;
+    let embedder = OpenAiEmbedder::new(format!("http://127.0.0.1:{}/v1", server.port), "test-key", "text-embedding-3-small")
+        .with_retry(RetryPolicy::none())
+        .with_timeout(Duration::from_millis(100));
+
+    let started = std::time::Instant::now();
+    assert!(embedder.embed("hello").await.is_err());
+    assert!(started.elapsed() < Duration::from_secs(2));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_an_embedding_with_a_different_dimension_stops_the_run() -> Result<()> {
+    let ollama = FakeOllama::start(|prompt| {
+        let embedding = if prompt == "odd one out" { vec![1.0] } else { vec![1.0, 2.0] };
+        (Duration::ZERO, Reply::Embedding(embedding))
+    })
+    .await;
+    let config = EmbeddingConfig::default().with_host("127.0.0.1", ollama.port);
+
+    let chunks = vec!["first", "odd one out", "third"].into_iter().map(String::from).collect();
+    let mut delivered = Vec::new();
+    let result = stream_embeddings(&config, chunks, None, |_| ControlFlow::Continue(()), |idx, _, embedding| {
+        delivered.push((idx, embedding.len()));
+        Ok(())
+    })
+    .await;
+    assert!(matches!(result, Err(CipherError::DimensionMismatch { expected: 2, actual: 1 })));
+    assert_eq!(delivered, vec![(0, 2)]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_a_bad_first_embedding_is_reported_rather_than_trusted() -> Result<()> {
+    let ollama = FakeOllama::start(|prompt| {
+        let embedding = if prompt == "first" { vec![1.0] } else { vec![1.0, 2.0] };
+        (Duration::ZERO, Reply::Embedding(embedding))
+    })
+    .await;
+    let config = EmbeddingConfig::default().with_host("127.0.0.1", ollama.port);
+
+    let chunks = vec!["first", "second", "third"].into_iter().map(String::from).collect();
+    let result = stream_embeddings(&config, chunks, None, |_| ControlFlow::Continue(()), |_, _, _| Ok(())).await;
+    assert!(matches!(result, Err(CipherError::DimensionMismatch { expected: 1, actual: 2 })));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_normalized_embeddings_have_unit_length() -> Result<()> {
+    let ollama = FakeOllama::start(|prompt| (Duration::ZERO, Reply::Embedding(vec![prompt.len() as f64, 3.0, 4.0]))).await;
+    let config = EmbeddingConfig::default()
+        .with_host("127.0.0.1", ollama.port)
+        .with_normalize(true);
+
+    let chunks = vec!["a", "longer chunk", "the longest chunk of all"].into_iter().map(String::from).collect();
+    let mut norms = Vec::new();
+    stream_embeddings(&config, chunks, None, |_| ControlFlow::Continue(()), |_, _, embedding| {
+        norms.push(embedding.iter().map(|x| x * x).sum::<f64>().sqrt());
+        Ok(())
+    })
+    .await?;
+    assert_eq!(norms.len(), 3);
+    assert!(norms.iter().all(|norm| (norm - 1.0).abs() < 1e-9));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prefixes_are_embedded_but_not_returned() -> Result<()> {
+    let prompts = Arc::new(Mutex::new(Vec::new()));
+    let ollama = FakeOllama::start({
+        let prompts = prompts.clone();
+        move |prompt| {
+            prompts.lock().unwrap().push(prompt.to_string());
+            (Duration::ZERO, Reply::Embedding(vec![1.0]))
+        }
+    })
+    .await;
+    let config = EmbeddingConfig::default().with_host("127.0.0.1", ollama.port);
+
+    let chunks = vec!["first".to_string(), "second".to_string()];
+    let prefixes = vec!["From 'Book', One: ".to_string(), "From 'Book', Two: ".to_string()];
+    let mut contents = Vec::new();
+    stream_embeddings(&config, chunks, Some(&prefixes), |_| ControlFlow::Continue(()), |_, content, _| {
+        contents.push(content);
+        Ok(())
+    })
+    .await?;
+    assert_eq!(contents, vec!["first", "second"]);
+    let mut prompts = prompts.lock().unwrap().clone();
+    prompts.sort();
+    assert_eq!(prompts, vec!["From 'Book', One: first", "From 'Book', Two: second"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_embedding_helpers_accept_any_embedder() -> Result<()> {
+    let embedder = HashEmbedder::new(8);
+    let config = EmbeddingConfig::default();
+
+    let single = get_single_embedding_with_embedder(&embedder, "brown rat").await?;
+    let chunks = vec!["brown rat".to_string(), "house mouse".to_string()];
+    let mut reported = 0;
+    let embeddings = get_embeddings_with_embedder(&embedder, &config, chunks, |progress| reported = progress.done).await?;
+    assert_eq!(embeddings.len(), 2);
+    assert_eq!(embeddings[0], single);
+    assert_eq!(reported, 2);
+    assert_eq!(embedder.call_count(), 3);
+    Ok(())
+}
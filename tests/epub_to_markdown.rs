@@ -1,5 +1,8 @@
 use anyhow::Result;
-use cipher::epub_to_markdown;
+use cipher::{
+    content_chunk_id, contextual_prefix, epub_chunks, epub_title, epub_toc, epub_to_markdown, epub_to_markdown_with, epub_to_markdown_with_metadata, ExtractOptions, HtmlBackend,
+    BoilerplateFilter, Html2Md, HtmlToMarkdown, ProseExtractor,
+};
 
 #[test]
 fn test_epub_to_markdown() -> Result<()> {
@@ -7,3 +10,155 @@ fn test_epub_to_markdown() -> Result<()> {
     assert!(!markdown_chunks.is_empty());
     Ok(())
 }
+
+#[test]
+fn test_epub_to_markdown_prose_backend() -> Result<()> {
+    let markdown_chunks = epub_to_markdown_with("testdata/pg35542.epub", &HtmlBackend::Prose, &ExtractOptions::default())?;
+    assert!(markdown_chunks.iter().any(|chunk| !chunk.trim().is_empty()));
+    Ok(())
+}
+
+#[test]
+fn test_prose_extractor_keeps_tables_and_headings() {
+    let html = "<html><body><h2>Results</h2><p>Some   text\n here.</p>\
+        <table><tr><th>Name</th><th>Age</th></tr><tr><td>Ann</td><td>30</td></tr></table>\
+        <script>ignored()</script></body></html>";
+    let markdown = ProseExtractor.convert(html);
+    assert_eq!(
+        markdown,
+        "## Results\n\nSome text here.\n\n| Name | Age |\n| --- | --- |\n| Ann | 30 |"
+    );
+}
+
+#[test]
+fn test_prose_extractor_keeps_text_outside_paragraphs() {
+    let html = "<html><body><div>It was a <em>dark</em> and stormy night.<br/>The rats came.</div>\
+        <p>Para.</p>Trailing words.</body></html>";
+    let markdown = ProseExtractor.convert(html);
+    assert_eq!(markdown, "It was a dark and stormy night. The rats came.\n\nPara.\n\nTrailing words.");
+}
+
+#[test]
+fn test_epub_to_markdown_segments_large_resources() -> Result<()> {
+    let whole = epub_to_markdown("testdata/pg35542.epub")?;
+    let options = ExtractOptions {
+        large_resource_bytes: 4 * 1024,
+        ..ExtractOptions::default()
+    };
+    let segmented = epub_to_markdown_with("testdata/pg35542.epub", &HtmlBackend::Html2Md, &options)?;
+    assert_eq!(segmented.len(), whole.len());
+    let longest = segmented.iter().max_by_key(|chunk| chunk.len()).unwrap();
+    assert!(longest.contains("brown rat"));
+    Ok(())
+}
+
+#[test]
+fn test_epub_title_builds_contextual_prefix() -> Result<()> {
+    let title = epub_title("testdata/pg35542.epub")?.expect("title metadata");
+    assert_eq!(contextual_prefix(&title, None), "From 'House Rats and Mice': ");
+    assert_eq!(contextual_prefix(&title, Some("TRAPS.")), "From 'House Rats and Mice', TRAPS: ");
+    Ok(())
+}
+
+#[test]
+fn test_epub_to_markdown_with_metadata_tags_spine_items() -> Result<()> {
+    let plain = epub_to_markdown("testdata/pg35542.epub")?;
+    let chunks = epub_to_markdown_with_metadata("testdata/pg35542.epub")?;
+    assert_eq!(chunks.len(), plain.len());
+    for (idx, (chunk, metadata)) in chunks.iter().enumerate() {
+        assert_eq!(chunk, &plain[idx]);
+        assert!(!metadata["spine_id"].is_empty());
+        assert!(metadata["chapter_index"].parse::<usize>().is_ok());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_content_chunk_ids_are_stable_across_rebuilds() -> Result<()> {
+    let ids = || -> Result<Vec<String>> {
+        let chunks = epub_to_markdown("testdata/pg35542.epub")?;
+        Ok(chunks
+            .iter()
+            .enumerate()
+            .map(|(idx, chunk)| content_chunk_id("pg35542.epub", idx, chunk))
+            .collect())
+    };
+    let first = ids()?;
+    assert_eq!(first, ids()?);
+    assert_ne!(first[0], first[1]);
+    assert_ne!(content_chunk_id("a.epub", 0, "text"), content_chunk_id("b.epub", 0, "text"));
+    assert_ne!(content_chunk_id("a.epub", 0, "text"), content_chunk_id("a.epub", 0, "other text"));
+    Ok(())
+}
+
+#[test]
+fn test_epub_toc_reads_ncx() -> Result<()> {
+    let toc = epub_toc("testdata/pg35542.epub")?;
+    assert_eq!(toc[0].title, "HOUSE RATS AND MICE");
+    assert_eq!(toc[0].href, "OEBPS/2646190561790918633_35542-h-0.htm.html#pgepubid00001");
+    assert_eq!(toc[0].order, 1);
+    // Nested entries are flattened in reading order.
+    assert_eq!(toc[3].title, "UNITED STATES DEPARTMENT OF AGRICULTURE");
+    assert!(toc.windows(2).all(|pair| pair[0].order < pair[1].order));
+    Ok(())
+}
+
+#[test]
+fn test_epub_toc_falls_back_to_nav_document() -> Result<()> {
+    let toc = epub_toc("testdata/pg35542-nav-only.epub")?;
+    assert_eq!(toc[0].title, "HOUSE RATS AND MICE");
+    assert_eq!(toc[0].resource(), "OEBPS/2646190561790918633_35542-h-0.htm.xhtml");
+    assert_eq!(toc[3].title, "UNITED STATES DEPARTMENT OF AGRICULTURE");
+    Ok(())
+}
+
+#[test]
+fn test_chunks_carry_their_toc_title() -> Result<()> {
+    let chunks = epub_to_markdown_with_metadata("testdata/pg35542.epub")?;
+    let (_, cover) = &chunks[0];
+    assert_eq!(cover.get("toc_title"), None);
+    let (_, first_chapter) = chunks
+        .iter()
+        .find(|(_, metadata)| metadata["spine_id"] == "pg-header")
+        .unwrap();
+    assert_eq!(first_chapter["toc_title"], "HOUSE RATS AND MICE");
+    Ok(())
+}
+
+#[test]
+fn test_skipped_titles_do_not_drop_the_untitled_items_after_them() -> Result<()> {
+    let options = ExtractOptions {
+        boilerplate: Some(BoilerplateFilter::default()),
+        ..ExtractOptions::default()
+    };
+    let chunks = epub_chunks("testdata/untitled-chapters.epub", &Html2Md, &options)?;
+    let spine_ids: Vec<&str> = chunks.iter().map(|(_, metadata)| metadata["spine_id"].as_str()).collect();
+    assert_eq!(spine_ids, vec!["chapter1", "chapter2"]);
+    assert!(chunks[0].0.contains("dark and stormy night"));
+    Ok(())
+}
+
+#[test]
+fn test_boilerplate_filter_keeps_the_narrative() -> Result<()> {
+    let plain = epub_to_markdown("testdata/pg35542.epub")?.join("\n");
+    assert!(plain.contains("FULL PROJECT GUTENBERG LICENSE"));
+
+    let options = ExtractOptions {
+        boilerplate: Some(BoilerplateFilter::default()),
+        ..ExtractOptions::default()
+    };
+    let chunks = epub_to_markdown_with("testdata/pg35542.epub", &Html2Md, &options)?;
+    let text = chunks.join("\n");
+    assert!(!text.contains("FULL PROJECT GUTENBERG LICENSE"));
+    assert!(!text.contains("START OF THE PROJECT GUTENBERG"));
+    assert!(!text.contains("END OF THE PROJECT GUTENBERG"));
+    assert!(text.contains("DESTRUCTIVE HABITS OF HOUSE RATS AND MICE"));
+    assert!(text.to_lowercase().contains("brown rat"));
+
+    let spine_ids: Vec<String> = epub_chunks("testdata/pg35542.epub", &Html2Md, &options)?
+        .into_iter()
+        .map(|(_, metadata)| metadata["spine_id"].clone())
+        .collect();
+    assert_eq!(spine_ids, vec!["coverpage-wrapper", "pg-header", "item4"]);
+    Ok(())
+}
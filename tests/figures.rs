@@ -0,0 +1,21 @@
+use anyhow::Result;
+use cipher::epub_figures;
+
+#[test]
+fn test_epub_figures_reads_captions() -> Result<()> {
+    let figures = epub_figures("testdata/pg35542-images.epub")?;
+    let rat = figures
+        .iter()
+        .find(|figure| figure.caption.contains("Brown rat"))
+        .expect("captioned figure");
+    assert!(rat.resource.ends_with("fig-01-400.png"));
+    assert!(rat.to_markdown().starts_with("![Fig. 1.—Brown rat.]("));
+    Ok(())
+}
+
+#[test]
+fn test_epub_figures_skips_books_without_images() -> Result<()> {
+    let figures = epub_figures("testdata/pg35542.epub")?;
+    assert!(figures.is_empty());
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use anyhow::Result;
+use cipher::{epub_to_markdown_with, normalize_markdown, ExtractOptions, Html2Md};
+
+#[test]
+fn test_messy_markdown_is_cleaned() {
+    let messy = "  # The&nbsp;Title  \n\n\n\nSome   text\twith&nbsp;&nbsp;gaps\u{a0}and &lt;tags&gt; &amp; more.   \n\n\n\n    indented   code\n\n&amp;lt; stays escaped\n\n";
+    let cleaned = normalize_markdown(messy);
+    assert_eq!(
+        cleaned,
+        "# The Title\n\nSome text with gaps and <tags> & more.\n\n    indented code\n\n&amp;lt; stays escaped"
+    );
+    assert_eq!(normalize_markdown(&cleaned), cleaned);
+}
+
+#[test]
+fn test_normalized_epub_chunks_have_no_runs_of_blank_lines() -> Result<()> {
+    let options = ExtractOptions {
+        normalize: true,
+        ..ExtractOptions::default()
+    };
+    let chunks = epub_to_markdown_with("testdata/pg35542.epub", &Html2Md, &options)?;
+    assert!(!chunks.is_empty());
+    for chunk in &chunks {
+        assert!(!chunk.contains("\n\n\n") && !chunk.contains("&nbsp;"));
+        assert_eq!(&normalize_markdown(chunk), chunk);
+    }
+    Ok(())
+}
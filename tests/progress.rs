@@ -0,0 +1,116 @@
+mod common;
+
+use cipher::{stream_embeddings_with_embedder, EmbeddingCache, EmbeddingConfig, EtaEstimator, Progress};
+use common::HashEmbedder;
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+#[test]
+fn test_progress_display_with_eta() {
+    let progress = Progress {
+        done: 1234,
+        total: 5000,
+        eta: Some(Duration::from_secs(170)),
+    };
+    assert_eq!(progress.to_string(), "1234/5000 (24%) ~3m remaining");
+}
+
+#[test]
+fn test_progress_bar() {
+    let bar = |done, total| Progress { done, total, eta: None }.bar(10);
+    assert_eq!(bar(0, 4), "[          ]");
+    assert_eq!(bar(1, 4), "[=>        ]");
+    assert_eq!(bar(2, 4), "[====>     ]");
+    assert_eq!(bar(4, 4), "[==========]");
+    assert_eq!(bar(0, 0), "[==========]");
+}
+
+#[test]
+fn test_eta_estimator_uses_rolling_average() {
+    let mut estimator = EtaEstimator::new(2);
+    assert_eq!(estimator.estimate(10), None);
+
+    estimator.record(Duration::from_secs(10));
+    estimator.record(Duration::from_secs(2));
+    estimator.record(Duration::from_secs(4));
+    assert_eq!(estimator.estimate(5), Some(Duration::from_secs(15)));
+}
+
+#[tokio::test]
+async fn test_stream_embeddings_stops_on_break() -> anyhow::Result<()> {
+    let chunks = vec!["first".to_string(), "second".to_string(), "third".to_string()];
+    let mut reported = Vec::new();
+    let embedder = HashEmbedder::new(8);
+    stream_embeddings_with_embedder(
+        &embedder,
+        &EmbeddingConfig::default().with_concurrency(1),
+        chunks,
+        None,
+        |progress| {
+            reported.push(progress.done);
+            ControlFlow::Break(())
+        },
+        |_, _, _| Ok(()),
+    )
+    .await?;
+    assert_eq!(reported, vec![1]);
+    assert_eq!(embedder.call_count(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stream_embeddings_finishes_in_flight_chunks_on_break() -> anyhow::Result<()> {
+    let chunks = (0..5).map(|i| format!("chunk {}", i)).collect();
+    let mut reported = Vec::new();
+    let embedder = HashEmbedder::new(8);
+    stream_embeddings_with_embedder(
+        &embedder,
+        &EmbeddingConfig::default().with_concurrency(2),
+        chunks,
+        None,
+        |progress| {
+            reported.push(progress.done);
+            ControlFlow::Break(())
+        },
+        |_, _, _| Ok(()),
+    )
+    .await?;
+    assert_eq!(reported, vec![1, 2]);
+    assert_eq!(embedder.call_count(), 2);
+    Ok(())
+}
+
+async fn reported_etas(embedder: &HashEmbedder, config: &EmbeddingConfig, chunks: Vec<String>) -> anyhow::Result<Vec<Option<Duration>>> {
+    let mut etas = Vec::new();
+    stream_embeddings_with_embedder(
+        embedder,
+        config,
+        chunks,
+        None,
+        |progress| {
+            etas.push(progress.eta);
+            ControlFlow::Continue(())
+        },
+        |_, _, _| Ok(()),
+    )
+    .await?;
+    Ok(etas)
+}
+
+#[tokio::test]
+async fn test_cache_hits_do_not_count_towards_the_eta() -> anyhow::Result<()> {
+    let cache_dir = std::env::temp_dir().join(format!("cipher-eta-cache-{}", std::process::id()));
+    let config = EmbeddingConfig::default().with_cache(EmbeddingCache::new(&cache_dir));
+    let chunks: Vec<String> = (0..3).map(|i| format!("chunk {}", i)).collect();
+    let embedder = HashEmbedder::new(8);
+
+    let etas = reported_etas(&embedder, &config, chunks.clone()).await?;
+    assert!(etas.iter().all(Option::is_some));
+
+    let etas = reported_etas(&embedder, &config, chunks).await?;
+    assert_eq!(embedder.call_count(), 3);
+    assert!(etas.iter().all(Option::is_none));
+
+    std::fs::remove_dir_all(&cache_dir)?;
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use cipher::{cosine_similarity, dot_product, euclidean_distance, l2_normalize, CipherError};
+
+#[test]
+fn test_cosine_similarity_known_vectors() -> Result<()> {
+    assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0])?, 0.0);
+    assert!((cosine_similarity(&[1.0, 2.0, 3.0], &[1.0, 2.0, 3.0])? - 1.0).abs() < 1e-12);
+    assert!((cosine_similarity(&[1.0, 2.0], &[-1.0, -2.0])? + 1.0).abs() < 1e-12);
+    assert!((cosine_similarity(&[1.0, 1.0], &[3.0, 3.0])? - 1.0).abs() < 1e-12);
+    assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0])?, 0.0);
+    Ok(())
+}
+
+#[test]
+fn test_dot_product_and_euclidean_distance() -> Result<()> {
+    assert_eq!(dot_product(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0])?, 32.0);
+    assert_eq!(euclidean_distance(&[0.0, 0.0], &[3.0, 4.0])?, 5.0);
+    assert_eq!(euclidean_distance(&[1.0, 2.0], &[1.0, 2.0])?, 0.0);
+    Ok(())
+}
+
+#[test]
+fn test_mismatched_lengths_are_errors() {
+    let err = cosine_similarity(&[1.0, 0.0], &[1.0, 0.0, 0.0]).unwrap_err();
+    assert!(matches!(err, CipherError::DimensionMismatch { expected: 2, actual: 3 }));
+    assert!(matches!(dot_product(&[1.0], &[1.0, 2.0]), Err(CipherError::DimensionMismatch { .. })));
+    assert!(matches!(euclidean_distance(&[1.0], &[1.0, 2.0]), Err(CipherError::DimensionMismatch { .. })));
+}
+
+#[test]
+fn test_l2_normalize_scales_to_unit_length() {
+    let mut v = vec![3.0, 4.0];
+    l2_normalize(&mut v);
+    assert_eq!(v, vec![0.6, 0.8]);
+    let mut zero = vec![0.0, 0.0];
+    l2_normalize(&mut zero);
+    assert_eq!(zero, vec![0.0, 0.0]);
+}